@@ -11,8 +11,11 @@ fn main() {
         );
 
         //List all key values without a label (all key values);
-        let key_values = app_config_client.list_key_values(SearchLabel::All).await;
-        for k in key_values {
+        let key_values = app_config_client
+            .list_key_values(SearchLabel::All)
+            .await
+            .unwrap();
+        for k in key_values.items {
             println!("{:?}", k);
         }
     })
@@ -0,0 +1,23 @@
+use async_std::task;
+use azure_app_configuration::client::AzureAppConfigClient;
+use azure_app_configuration::search_label::SearchLabel;
+use azure_app_configuration::select_field::SelectField;
+
+fn main() {
+    task::block_on(async {
+        let app_config_client = AzureAppConfigClient::new(
+            "https://lande-app-configuration.azconfig.io",
+            "0-l9-s0:Z6DMwn2DoiKxgVsTIm7h",
+            "wgf9BDWeh/+Dtq8DmpsJSUpwrdgYLrXG8svE+VyM06w=",
+        );
+
+        //List only keys and etags of all key values
+        let key_values = app_config_client
+            .list_key_values_select(SearchLabel::All, &[SelectField::Key, SelectField::Etag])
+            .await
+            .unwrap();
+        for k in key_values.items {
+            println!("{:?} {:?}", k.key, k.etag);
+        }
+    })
+}
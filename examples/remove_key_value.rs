@@ -19,11 +19,12 @@ fn main() {
             .await
             .unwrap();
 
-        //Remove the key value
-        app_config_client
+        //Remove the key value, getting back the removed one
+        let removed = app_config_client
             .remove_key_value("EnableHttps", SearchLabel::All)
             .await
             .unwrap();
+        println!("{:?}", removed);
 
         //Create a key with a label
         let _kv = app_config_client
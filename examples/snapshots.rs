@@ -0,0 +1,41 @@
+use async_std::task;
+use azure_app_configuration::client::AzureAppConfigClient;
+use azure_app_configuration::model::{CompositionType, SnapshotFilter};
+
+fn main() {
+    task::block_on(async {
+        let app_config_client = AzureAppConfigClient::new(
+            "https://lande-app-configuration.azconfig.io",
+            "0-l9-s0:Z6DMwn2DoiKxgVsTIm7h",
+            "wgf9BDWeh/+Dtq8DmpsJSUpwrdgYLrXG8svE+VyM06w=",
+        );
+
+        //Create a snapshot with all ContosoApp key values and wait until it is ready
+        let snapshot = app_config_client
+            .create_snapshot(
+                "release-1.0",
+                &[SnapshotFilter::new("*", Some("ContosoApp"))],
+                CompositionType::Key,
+                None,
+            )
+            .await
+            .unwrap();
+        println!("{:?}", snapshot);
+
+        //Read the key values stored in the snapshot
+        let key_values = app_config_client
+            .list_snapshot_key_values("release-1.0")
+            .await
+            .unwrap();
+        for k in key_values.items {
+            println!("{:?}", k);
+        }
+
+        //Archive the snapshot
+        let snapshot = app_config_client
+            .archive_snapshot("release-1.0")
+            .await
+            .unwrap();
+        println!("{:?}", snapshot.status);
+    })
+}
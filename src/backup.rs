@@ -0,0 +1,483 @@
+//! Full store backup and restore
+//!
+//! Backups are newline delimited json archives. The first line is a header with
+//! the archive format version, followed by one line per key value and, optionally,
+//! one line per revision. Revisions are kept for reference, `restore` only
+//! recreates the current key values.
+use crate::client::{AzureAppConfigClient, Precondition};
+use crate::endpoints::EndpointUrl;
+use crate::error::{HttpError, LOCKED_STATUS, PRECONDITION_FAILED_STATUS};
+use crate::model::KeyValue;
+use crate::search_label::SearchLabel;
+use crate::Exception;
+use futures::stream::{self, StreamExt};
+use std::error::Error;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const ARCHIVE_FORMAT: &str = "azure-app-configuration-backup";
+const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header {
+        format: String,
+        version: u32,
+        endpoint: String,
+        created: String,
+    },
+    KeyValue(KeyValue),
+    Revision(KeyValue),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupOptions {
+    /// Also archive the revision history of every key value
+    pub include_revisions: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupSummary {
+    pub key_values: usize,
+    pub revisions: usize,
+}
+
+/// What to do when a key value being restored already exists in the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing key value
+    Skip,
+    /// Replace the existing key value, unlocking it if needed
+    Overwrite,
+    /// Stop the restore, failing with a `RestoreConflictError`
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    pub conflict: ConflictPolicy,
+    /// Lock again the key values that were locked when the backup was taken
+    pub relock: bool,
+    /// Maximum number of key values restored at the same time
+    pub max_concurrency: usize,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            conflict: ConflictPolicy::Skip,
+            relock: true,
+            max_concurrency: 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RestoreOutcome {
+    Restored(KeyValue),
+    SkippedExisting,
+    Failed(Exception),
+}
+
+#[derive(Debug)]
+pub struct RestoreItemResult {
+    pub key: String,
+    pub label: Option<String>,
+    pub outcome: RestoreOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    /// Results in archive order
+    pub items: Vec<RestoreItemResult>,
+}
+
+impl RestoreReport {
+    pub fn restored(&self) -> impl Iterator<Item = &RestoreItemResult> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, RestoreOutcome::Restored(_)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &RestoreItemResult> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, RestoreOutcome::Failed(_)))
+    }
+}
+
+/// Restore stopped by `ConflictPolicy::Fail` on an existing key value. The report
+/// holds every key value attempted before stopping, including the conflicting one
+#[derive(Debug)]
+pub struct RestoreConflictError {
+    key: String,
+    report: RestoreReport,
+}
+
+impl RestoreConflictError {
+    /// First key found to already exist
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn report(&self) -> &RestoreReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> RestoreReport {
+        self.report
+    }
+}
+
+impl Error for RestoreConflictError {}
+
+impl fmt::Display for RestoreConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Key {} already exists, restore stopped after {} key values",
+            self.key,
+            self.report.items.len()
+        )
+    }
+}
+
+/// Writes every key value of the store, all keys and labels including feature
+/// flags and Key Vault references, to the archive as pages are retrieved
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::backup::{backup, BackupOptions};
+///
+/// let file = std::fs::File::create("store.backup")?;
+/// let summary = backup(&app_config_client, file, &BackupOptions::default()).await?;
+/// println!("{} key values archived", summary.key_values);
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(include_revisions = options.include_revisions))
+)]
+pub async fn backup<W: Write>(
+    client: &AzureAppConfigClient,
+    mut writer: W,
+    options: &BackupOptions,
+) -> Result<BackupSummary, Exception> {
+    let mut summary = BackupSummary::default();
+
+    write_record(
+        &mut writer,
+        &Record::Header {
+            format: ARCHIVE_FORMAT.to_owned(),
+            version: ARCHIVE_VERSION,
+            endpoint: client.endpoint_uri(),
+            created: httpdate::fmt_http_date(std::time::SystemTime::now()),
+        },
+    )?;
+
+    client
+        .list_all(EndpointUrl::KeyValues, |items: Vec<KeyValue>| {
+            summary.key_values += items.len();
+            for kv in items {
+                write_record(&mut writer, &Record::KeyValue(kv))?;
+            }
+            Ok(())
+        })
+        .await?;
+
+    if options.include_revisions {
+        client
+            .list_all(EndpointUrl::Revisions, |items: Vec<KeyValue>| {
+                summary.revisions += items.len();
+                for kv in items {
+                    write_record(&mut writer, &Record::Revision(kv))?;
+                }
+                Ok(())
+            })
+            .await?;
+    }
+
+    writer.flush()?;
+    Ok(summary)
+}
+
+/// Recreates the key values of an archive in the store, applying the conflict
+/// policy to existing key values and locking again the ones that were locked.
+/// The archive is read whole before any write, then up to `max_concurrency` key
+/// values are restored at the same time. With `ConflictPolicy::Fail` no key value
+/// is started after the first existing one, the ones in flight are finished and
+/// reported in the `RestoreConflictError`
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::backup::{restore, RestoreOptions};
+///
+/// let file = std::io::BufReader::new(std::fs::File::open("store.backup")?);
+/// let report = restore(&app_config_client, file, &RestoreOptions::default()).await?;
+/// println!("{} key values restored", report.restored().count());
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(conflict = ?options.conflict, relock = options.relock))
+)]
+pub async fn restore<R: BufRead>(
+    client: &AzureAppConfigClient,
+    reader: R,
+    options: &RestoreOptions,
+) -> Result<RestoreReport, Exception> {
+    let mut lines = reader.lines();
+
+    match lines.next() {
+        Some(line) => match serde_json::from_str::<Record>(&line?)? {
+            Record::Header {
+                ref format,
+                version,
+                ..
+            } if format == ARCHIVE_FORMAT && (1..=ARCHIVE_VERSION).contains(&version) => {}
+            Record::Header { version, .. } => {
+                return Err(format!("Unsupported backup archive version {}", version).into())
+            }
+            _ => return Err("Backup archive header is missing".into()),
+        },
+        None => return Err("Backup archive is empty".into()),
+    }
+
+    let mut key_values = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Record::KeyValue(kv) = serde_json::from_str::<Record>(&line)? {
+            key_values.push(kv);
+        }
+    }
+
+    let stop = AtomicBool::new(false);
+    let items: Vec<RestoreItemResult> = stream::iter(
+        key_values
+            .into_iter()
+            .take_while(|_| !stop.load(Ordering::SeqCst)),
+    )
+    .map(|kv| async {
+        let outcome = restore_key_value(client, &kv, options).await;
+        if options.conflict == ConflictPolicy::Fail {
+            if let RestoreOutcome::SkippedExisting = outcome {
+                stop.store(true, Ordering::SeqCst);
+            }
+        }
+
+        RestoreItemResult {
+            key: kv.key,
+            label: kv.label,
+            outcome,
+        }
+    })
+    .buffered(options.max_concurrency.max(1))
+    .collect()
+    .await;
+
+    let report = RestoreReport { items };
+    let conflict = match options.conflict {
+        ConflictPolicy::Fail => report
+            .items
+            .iter()
+            .find(|i| matches!(i.outcome, RestoreOutcome::SkippedExisting))
+            .map(|i| i.key.clone()),
+        _ => None,
+    };
+
+    match conflict {
+        Some(key) => Err(RestoreConflictError { key, report }.into()),
+        None => Ok(report),
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(key = %kv.key, label = kv.label.as_deref().unwrap_or(""))
+    )
+)]
+async fn restore_key_value(
+    client: &AzureAppConfigClient,
+    kv: &KeyValue,
+    options: &RestoreOptions,
+) -> RestoreOutcome {
+    let label = || match &kv.label {
+        Some(l) => SearchLabel::For(l),
+        None => SearchLabel::All,
+    };
+
+    let precondition = match options.conflict {
+        ConflictPolicy::Overwrite => None,
+        _ => Some(Precondition::IfNoneMatch),
+    };
+
+    let mut result = client
+        .put_key_value(kv.key.clone(), label(), kv, precondition.as_ref())
+        .await;
+
+    if options.conflict == ConflictPolicy::Overwrite && status(&result) == Some(LOCKED_STATUS) {
+        result = match client.unlock_key_value(kv.key.clone(), label()).await {
+            Ok(_) => {
+                client
+                    .put_key_value(kv.key.clone(), label(), kv, None)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+    }
+
+    if status(&result) == Some(PRECONDITION_FAILED_STATUS) {
+        return RestoreOutcome::SkippedExisting;
+    }
+
+    let restored = match result {
+        Ok(restored) => restored.into_value(),
+        Err(e) => return RestoreOutcome::Failed(e),
+    };
+
+    if options.relock && kv.locked {
+        return match client.lock_key_value(kv.key.clone(), label()).await {
+            Ok(locked) => RestoreOutcome::Restored(locked),
+            Err(e) => RestoreOutcome::Failed(e),
+        };
+    }
+
+    RestoreOutcome::Restored(restored)
+}
+
+fn status<T>(result: &Result<T, Exception>) -> Option<usize> {
+    match result {
+        Err(e) => e.downcast_ref::<HttpError>().map(|h| h.status()),
+        Ok(_) => None,
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, record: &Record) -> Result<(), Exception> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+#[test]
+fn records_should_be_tagged_by_type() {
+    let kv = KeyValue {
+        key: "app:port".to_owned(),
+        value: "8080".to_owned(),
+        locked: true,
+        ..Default::default()
+    };
+
+    let line = serde_json::to_string(&Record::KeyValue(kv)).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(value["type"], "key_value");
+    assert_eq!(value["key"], "app:port");
+    assert_eq!(value["locked"], true);
+
+    match serde_json::from_str::<Record>(&line).unwrap() {
+        Record::KeyValue(kv) => assert_eq!(kv.value, "8080"),
+        _ => panic!("Expected a key value record"),
+    }
+}
+
+#[test]
+fn restore_should_reject_archives_without_header() {
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0");
+    let archive = "{\"type\":\"key_value\",\"key\":\"k\",\"value\":\"v\",\"label\":null,\"content_type\":null}\n";
+
+    let result = async_std::task::block_on(restore(
+        &client,
+        archive.as_bytes(),
+        &RestoreOptions::default(),
+    ));
+    assert!(result.is_err());
+}
+
+#[test]
+fn restore_should_reject_unknown_archive_versions() {
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0");
+    let header = |version: u32| {
+        format!(
+            "{{\"type\":\"header\",\"format\":\"{}\",\"version\":{},\"endpoint\":\"\",\"created\":\"\"}}\n",
+            ARCHIVE_FORMAT, version
+        )
+    };
+
+    for version in &[0, ARCHIVE_VERSION + 1] {
+        let error = async_std::task::block_on(restore(
+            &client,
+            header(*version).as_bytes(),
+            &RestoreOptions::default(),
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("Unsupported backup archive version {}", version)
+        );
+    }
+
+    let report = async_std::task::block_on(restore(
+        &client,
+        header(ARCHIVE_VERSION).as_bytes(),
+        &RestoreOptions::default(),
+    ))
+    .unwrap();
+    assert_eq!(report.restored().count(), 0);
+}
+
+#[test]
+fn failing_restore_should_report_the_key_values_written_before_the_conflict() {
+    use crate::pipeline::{PipelineRequest, PipelineResponse, Policy, PolicyContext};
+    use std::sync::{Arc, Mutex};
+
+    /// Answers with a 412 status for the existing key, recording every written path
+    #[derive(Default)]
+    struct Existing(Mutex<Vec<String>>);
+
+    impl Policy for Existing {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let path = request.url.path().to_owned();
+            self.0.lock().unwrap().push(path.clone());
+            Ok(Some(match path.as_str() {
+                "/kv/existing" => PipelineResponse::new(412, ""),
+                _ => PipelineResponse::new(
+                    200,
+                    r#"{"key":"new","value":"1","label":null,"content_type":null}"#,
+                ),
+            }))
+        }
+    }
+
+    let existing = Arc::new(Existing::default());
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![existing.clone()]);
+    let archive = [
+        r#"{"type":"header","format":"azure-app-configuration-backup","version":1,"endpoint":"https://sample.azconfig.io","created":"Tue, 05 Dec 2017 02:41:26 GMT"}"#,
+        r#"{"type":"key_value","key":"new","value":"1","label":null,"content_type":null}"#,
+        r#"{"type":"key_value","key":"existing","value":"2","label":null,"content_type":null}"#,
+        r#"{"type":"key_value","key":"never","value":"3","label":null,"content_type":null}"#,
+    ]
+    .join("\n");
+    let options = RestoreOptions {
+        conflict: ConflictPolicy::Fail,
+        max_concurrency: 1,
+        ..Default::default()
+    };
+
+    let error = async_std::task::block_on(restore(&client, archive.as_bytes(), &options))
+        .unwrap_err()
+        .downcast::<RestoreConflictError>()
+        .unwrap();
+
+    assert_eq!(error.key(), "existing");
+    assert_eq!(error.report().restored().count(), 1);
+    assert_eq!(error.report().items.len(), 2);
+    assert_eq!(*existing.0.lock().unwrap(), vec!["/kv/new", "/kv/existing"]);
+}
@@ -0,0 +1,335 @@
+use crate::client::AzureAppConfigClient;
+use crate::error::{HttpError, LOCKED_STATUS};
+use crate::model::KeyValue;
+use crate::search_label::SearchLabel;
+use crate::Exception;
+use futures::stream::{self, StreamExt};
+
+/// Outcome of a single batch item
+#[derive(Debug)]
+pub enum BatchOutcome<T> {
+    Succeeded(T),
+    Failed(Exception),
+    SkippedLocked,
+}
+
+#[derive(Debug)]
+pub struct BatchItemResult<T> {
+    pub key: String,
+    pub label: Option<String>,
+    pub outcome: BatchOutcome<T>,
+}
+
+/// Per item results of a batch operation, in the same order the items were provided
+#[derive(Debug)]
+pub struct BatchReport<T> {
+    pub items: Vec<BatchItemResult<T>>,
+}
+
+impl<T> BatchReport<T> {
+    pub fn succeeded(&self) -> impl Iterator<Item = &BatchItemResult<T>> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, BatchOutcome::Succeeded(_)))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BatchItemResult<T>> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, BatchOutcome::Failed(_)))
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &BatchItemResult<T>> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, BatchOutcome::SkippedLocked))
+    }
+
+    /// Returns true when no item failed, skipped items are not considered failures
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none()
+    }
+}
+
+impl AzureAppConfigClient {
+    /// Set several key values running up to `max_concurrency` requests at the same time.
+    /// Every key value is attempted, failures do not stop the batch
+    /// # Arguments
+    /// * `key_values` - Key values to be set, with their label, tags and content type
+    /// * `max_concurrency` - Maximum number of requests in flight
+    /// # Examples
+    /// ```no run
+    /// let report = app_config_client.set_key_values(key_values, 8).await;
+    /// for item in report.failed() {
+    ///     println!("{} failed: {:?}", item.key, item.outcome);
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(max_concurrency))
+    )]
+    pub async fn set_key_values<I>(
+        &self,
+        key_values: I,
+        max_concurrency: usize,
+    ) -> BatchReport<KeyValue>
+    where
+        I: IntoIterator<Item = KeyValue>,
+    {
+        let items = stream::iter(key_values)
+            .map(|kv| async move {
+                let label = match &kv.label {
+                    Some(l) => SearchLabel::For(l),
+                    None => SearchLabel::All,
+                };
+                let result = self
+                    .set_key(
+                        kv.key.clone(),
+                        kv.value,
+                        label,
+                        Some(kv.tags),
+                        kv.content_type,
+                    )
+                    .await;
+
+                BatchItemResult {
+                    key: kv.key,
+                    label: kv.label,
+                    outcome: into_outcome(result),
+                }
+            })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await;
+
+        BatchReport { items }
+    }
+
+    /// Remove several key values running up to `max_concurrency` requests at the same time.
+    /// Every key value is attempted, failures do not stop the batch. Succeeded items hold
+    /// the removed key value, `None` when it did not exist
+    /// # Arguments
+    /// * `keys` - Key and label pairs to be removed, `None` for key values without label
+    /// * `max_concurrency` - Maximum number of requests in flight
+    /// # Examples
+    /// ```no run
+    /// let report = app_config_client
+    ///     .remove_key_values(vec![("EnableProxy", Some("ApplicationLabel"))], 8)
+    ///     .await;
+    /// assert!(report.is_success());
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(max_concurrency))
+    )]
+    pub async fn remove_key_values<I, S>(
+        &self,
+        keys: I,
+        max_concurrency: usize,
+    ) -> BatchReport<Option<KeyValue>>
+    where
+        I: IntoIterator<Item = (S, Option<S>)>,
+        S: Into<String>,
+    {
+        let items = stream::iter(keys)
+            .map(|(key, label)| async move {
+                let key = key.into();
+                let label = label.map(|l| l.into());
+                let search_label = match &label {
+                    Some(l) => SearchLabel::For(l),
+                    None => SearchLabel::All,
+                };
+                let result = self.remove_key_value(key.clone(), search_label).await;
+
+                BatchItemResult {
+                    key,
+                    label,
+                    outcome: into_outcome(result),
+                }
+            })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await;
+
+        BatchReport { items }
+    }
+}
+
+fn into_outcome<T>(result: Result<T, Exception>) -> BatchOutcome<T> {
+    match result {
+        Ok(v) => BatchOutcome::Succeeded(v),
+        Err(e) => match e.downcast_ref::<HttpError>() {
+            Some(h) if h.status() == LOCKED_STATUS => BatchOutcome::SkippedLocked,
+            _ => BatchOutcome::Failed(e),
+        },
+    }
+}
+
+#[test]
+fn outcome_should_skip_locked_and_keep_other_errors() {
+    let locked: Result<(), Exception> = Err(HttpError::new(409, "http://sample.io/kv/key").into());
+    assert!(matches!(into_outcome(locked), BatchOutcome::SkippedLocked));
+
+    let failed: Result<(), Exception> = Err(HttpError::new(500, "http://sample.io/kv/key").into());
+    assert!(matches!(into_outcome(failed), BatchOutcome::Failed(_)));
+
+    assert!(matches!(into_outcome(Ok(())), BatchOutcome::Succeeded(())));
+}
+
+/// Serves every request on its own thread after a delay depending on the key: `locked`
+/// answers 409, `broken` 500, `gone` 204 and `slow` is held longer than the others
+#[cfg(test)]
+fn serve_batch() -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+
+                let path = request_line.split_whitespace().nth(1).unwrap();
+                let key = path.trim_start_matches("/kv/").split('?').next().unwrap();
+                let delay = if key == "slow" { 150 } else { 50 };
+                std::thread::sleep(std::time::Duration::from_millis(delay));
+
+                let body = format!(
+                    r#"{{"key":"{}","value":"v","label":null,"content_type":null}}"#,
+                    key
+                );
+                let (status, body) = match key {
+                    "locked" => ("409 Conflict", ""),
+                    "broken" => ("500 Internal Server Error", ""),
+                    "gone" => ("204 No Content", ""),
+                    _ => ("200 OK", body.as_str()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            });
+        }
+    });
+
+    endpoint
+}
+
+#[test]
+fn batches_should_report_every_item_in_order_within_the_concurrency_limit() {
+    use crate::model::kv;
+    use crate::pipeline::{PipelineRequest, PipelineResponse, Policy, PolicyContext};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Counts the requests in flight, keeping the highest count
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl Policy for InFlight {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+            Ok(None)
+        }
+
+        fn on_response(
+            &self,
+            _context: &PolicyContext,
+            _request: &PipelineRequest,
+            _response: &mut PipelineResponse,
+        ) -> Result<Option<Duration>, Exception> {
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(None)
+        }
+    }
+
+    let in_flight = Arc::new(InFlight::default());
+    let client = AzureAppConfigClient::new(serve_batch().as_str(), "id", "c2VjcmV0")
+        .with_pipeline(vec![in_flight.clone()]);
+
+    let keys = ["slow", "locked", "first", "broken", "second", "third"];
+    let report = async_std::task::block_on(
+        client.set_key_values(keys.iter().map(|key| kv(key, "v")).collect::<Vec<_>>(), 2),
+    );
+
+    assert_eq!(
+        report
+            .items
+            .iter()
+            .map(|i| i.key.as_str())
+            .collect::<Vec<_>>(),
+        keys
+    );
+    assert!(matches!(&report.items[0].outcome, BatchOutcome::Succeeded(kv) if kv.key == "slow"));
+    assert!(matches!(
+        report.items[1].outcome,
+        BatchOutcome::SkippedLocked
+    ));
+    assert!(matches!(&report.items[3].outcome, BatchOutcome::Failed(e)
+        if e.downcast_ref::<HttpError>().unwrap().status() == 500));
+    assert_eq!(report.succeeded().count(), 4);
+    assert!(!report.is_success());
+    assert_eq!(in_flight.max.load(Ordering::SeqCst), 2);
+
+    in_flight.max.store(0, Ordering::SeqCst);
+    let report = async_std::task::block_on(client.remove_key_values(
+        vec![
+            ("gone", None),
+            ("locked", None),
+            ("first", Some("web")),
+            ("broken", None),
+        ],
+        3,
+    ));
+
+    assert_eq!(
+        report
+            .items
+            .iter()
+            .map(|i| i.key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["gone", "locked", "first", "broken"]
+    );
+    assert!(matches!(
+        report.items[0].outcome,
+        BatchOutcome::Succeeded(None)
+    ));
+    assert!(matches!(
+        report.items[1].outcome,
+        BatchOutcome::SkippedLocked
+    ));
+    assert!(
+        matches!(&report.items[2].outcome, BatchOutcome::Succeeded(Some(kv)) if kv.key == "first")
+    );
+    assert_eq!(report.items[2].label.as_deref(), Some("web"));
+    assert!(matches!(report.items[3].outcome, BatchOutcome::Failed(_)));
+    assert_eq!(in_flight.max.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,431 @@
+use async_std::task;
+use azure_app_configuration::client::AzureAppConfigClient;
+use azure_app_configuration::error::HttpError;
+use azure_app_configuration::formats::{self, Format, FormatOptions};
+use azure_app_configuration::model::KeyValue;
+use azure_app_configuration::search_label::SearchLabel;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::process;
+
+type Exception = Box<dyn Error + Send + Sync + 'static>;
+
+const CONNECTION_STRING_ENV: &str = "AZURE_APPCONFIG_CONNECTION_STRING";
+const IMPORT_CONCURRENCY: usize = 8;
+
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NOT_FOUND: i32 = 3;
+const EXIT_AUTH: i32 = 4;
+
+const USAGE: &str =
+    "Usage: appconfig [--connection-string <cs>] [--output table|json|yaml] <command>
+
+Commands:
+    kv list [--label <label>]
+    kv get <key> [--label <label>]
+    kv set <key> <value> [--label <label>] [--content-type <type>] [--tag <name>=<value>]...
+    kv delete <key> [--label <label>]
+    kv lock <key> [--label <label>]
+    kv unlock <key> [--label <label>]
+    label list
+    key list
+    import <file> --format <format> [--label <label>] [--separator <sep>] [--content-type <type>]
+    export <file> --format <format> [--label <label>] [--separator <sep>]
+
+Formats: json, yaml, toml, properties, dotenv, kvset. Use - as file for stdin/stdout.
+The connection string is read from AZURE_APPCONFIG_CONNECTION_STRING when no flag is given.
+
+Exit codes: 0 success, 1 error, 2 usage, 3 not found, 4 authentication failure";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Table,
+    Json,
+    Yaml,
+}
+
+/// Command line arguments split into positionals and `--name value` options
+struct Args {
+    positionals: Vec<String>,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Args, String> {
+        let mut positionals = Vec::new();
+        let mut options: HashMap<String, Vec<String>> = HashMap::new();
+
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some("help") => return Err(String::new()),
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for --{}", name))?;
+                    options.entry(name.to_string()).or_default().push(value);
+                }
+                None => positionals.push(arg),
+            }
+        }
+
+        Ok(Args {
+            positionals,
+            options,
+        })
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .get(name)
+            .and_then(|v| v.last())
+            .map(|v| v.as_str())
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positionals
+            .get(index)
+            .map(|p| p.as_str())
+            .ok_or_else(|| format!("Missing argument <{}>", name))
+    }
+
+    fn label(&self) -> SearchLabel<'_> {
+        match self.option("label") {
+            Some(l) => SearchLabel::For(l),
+            None => SearchLabel::All,
+        }
+    }
+}
+
+fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) if e.is_empty() => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => usage(&e),
+    };
+
+    let output = match args.option("output").unwrap_or("table") {
+        "table" => Output::Table,
+        "json" => Output::Json,
+        "yaml" => Output::Yaml,
+        other => usage(&format!("Unknown output {}", other)),
+    };
+
+    let connection_string = match args
+        .option("connection-string")
+        .map(|c| c.to_string())
+        .or_else(|| std::env::var(CONNECTION_STRING_ENV).ok())
+    {
+        Some(c) => c,
+        None => usage("Missing connection string"),
+    };
+
+    let client = match AzureAppConfigClient::from_connection_string(&connection_string) {
+        Ok(client) => client,
+        Err(e) => usage(&e.to_string()),
+    };
+
+    if let Err(e) = task::block_on(run(&client, &args, output)) {
+        eprintln!("{}", e);
+        process::exit(exit_code(&e));
+    }
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{}\n", message);
+    eprintln!("{}", USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+fn exit_code(e: &Exception) -> i32 {
+    match e.downcast_ref::<HttpError>().map(|h| h.status()) {
+        Some(404) => EXIT_NOT_FOUND,
+        Some(401) | Some(403) => EXIT_AUTH,
+        _ => EXIT_ERROR,
+    }
+}
+
+async fn run(client: &AzureAppConfigClient, args: &Args, output: Output) -> Result<(), Exception> {
+    let command = args
+        .positionals
+        .iter()
+        .take(2)
+        .map(|p| p.as_str())
+        .collect::<Vec<&str>>();
+
+    match command.as_slice() {
+        ["kv", "list"] => {
+            let key_values = client.list_all_key_values(args.label()).await?;
+            print_key_values(&key_values.items, output)
+        }
+        ["kv", "get"] => {
+            let kv = client
+                .get_key_value(args.positional(2, "key")?, args.label())
+                .await?;
+            print_key_values(&[kv], output)
+        }
+        ["kv", "set"] => {
+            let mut tags = HashMap::new();
+            for tag in args.options.get("tag").into_iter().flatten() {
+                let mut pair = tag.splitn(2, '=');
+                tags.insert(
+                    pair.next().unwrap_or("").to_string(),
+                    pair.next().unwrap_or("").to_string(),
+                );
+            }
+
+            let kv = client
+                .set_key(
+                    args.positional(2, "key")?.to_string(),
+                    args.positional(3, "value")?.to_string(),
+                    args.label(),
+                    Some(tags),
+                    args.option("content-type").map(|c| c.to_string()),
+                )
+                .await?;
+            print_key_values(&[kv], output)
+        }
+        ["kv", "delete"] => {
+            let removed = client
+                .remove_existing_key_value(args.positional(2, "key")?, args.label())
+                .await?;
+            print_key_values(&[removed], output)
+        }
+        ["kv", "lock"] => {
+            let kv = client
+                .lock_key_value(args.positional(2, "key")?, args.label())
+                .await?;
+            print_key_values(&[kv], output)
+        }
+        ["kv", "unlock"] => {
+            let kv = client
+                .unlock_key_value(args.positional(2, "key")?, args.label())
+                .await?;
+            print_key_values(&[kv], output)
+        }
+        ["label", "list"] => {
+            let labels = client.list_all_labels().await?;
+            let names = labels
+                .items
+                .iter()
+                .map(|l| l.name().map(|n| n.to_string()))
+                .collect::<Vec<Option<String>>>();
+            print_names("LABEL", &names, output)
+        }
+        ["key", "list"] => {
+            let keys = client.list_all_keys().await?;
+            let names = keys
+                .items
+                .iter()
+                .map(|k| Some(k.name().to_string()))
+                .collect::<Vec<Option<String>>>();
+            print_names("KEY", &names, output)
+        }
+        ["import", _] => import(client, args).await,
+        ["export", _] => export(client, args).await,
+        _ => Err("Unknown command, run appconfig --help".into()),
+    }
+}
+
+fn format_options(args: &Args) -> Result<(Format, FormatOptions), Exception> {
+    let format = match args.option("format") {
+        Some("json") => Format::Json,
+        Some("yaml") => Format::Yaml,
+        Some("toml") => Format::Toml,
+        Some("properties") => Format::Properties,
+        Some("dotenv") => Format::Dotenv,
+        Some("kvset") => Format::KvSet,
+        Some(other) => return Err(format!("Unknown format {}", other).into()),
+        None => return Err("Missing --format".into()),
+    };
+
+    let options = FormatOptions {
+        separator: args.option("separator").map(|s| s.to_string()),
+        label: args.option("label").map(|l| l.to_string()),
+        content_type: args.option("content-type").map(|c| c.to_string()),
+        ..Default::default()
+    };
+
+    Ok((format, options))
+}
+
+async fn import(client: &AzureAppConfigClient, args: &Args) -> Result<(), Exception> {
+    let (format, options) = format_options(args)?;
+    let file = args.positional(1, "file")?;
+
+    let input = if file == "-" {
+        let mut input = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut input)?;
+        input
+    } else {
+        std::fs::read_to_string(file)?
+    };
+
+    let key_values = formats::import(&input, format, &options)?;
+    let report = client.set_key_values(key_values, IMPORT_CONCURRENCY).await;
+
+    for item in report.skipped() {
+        eprintln!("Skipped locked key {}", item.key);
+    }
+    for item in report.failed() {
+        eprintln!("Failed to set key {}: {:?}", item.key, item.outcome);
+    }
+    println!(
+        "Imported {} key values, {} skipped, {} failed",
+        report.succeeded().count(),
+        report.skipped().count(),
+        report.failed().count()
+    );
+
+    if report.is_success() {
+        Ok(())
+    } else {
+        Err("Some key values could not be imported".into())
+    }
+}
+
+async fn export(client: &AzureAppConfigClient, args: &Args) -> Result<(), Exception> {
+    let (format, options) = format_options(args)?;
+    let file = args.positional(1, "file")?;
+
+    let key_values = client.list_all_key_values(args.label()).await?;
+    let exported = formats::export(&key_values.items, format, &options)?;
+
+    if file == "-" {
+        print!("{}", exported);
+    } else {
+        std::fs::write(file, exported)?;
+    }
+
+    Ok(())
+}
+
+fn print_key_values(key_values: &[KeyValue], output: Output) -> Result<(), Exception> {
+    match output {
+        Output::Table => {
+            let rows = key_values
+                .iter()
+                .map(|kv| {
+                    vec![
+                        kv.key.clone(),
+                        kv.label.clone().unwrap_or_default(),
+                        kv.value.clone(),
+                        kv.content_type.clone().unwrap_or_default(),
+                        kv.locked.to_string(),
+                    ]
+                })
+                .collect::<Vec<Vec<String>>>();
+            print_table(&["KEY", "LABEL", "VALUE", "CONTENT TYPE", "LOCKED"], &rows);
+            Ok(())
+        }
+        _ => print_value(&serde_json::to_value(key_values)?, output),
+    }
+}
+
+fn print_names(header: &str, names: &[Option<String>], output: Output) -> Result<(), Exception> {
+    match output {
+        Output::Table => {
+            let rows = names
+                .iter()
+                .map(|n| vec![n.clone().unwrap_or_default()])
+                .collect::<Vec<Vec<String>>>();
+            print_table(&[header], &rows);
+            Ok(())
+        }
+        _ => print_value(&json!(names), output),
+    }
+}
+
+fn print_value(value: &Value, output: Output) -> Result<(), Exception> {
+    match output {
+        Output::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        _ => println!("{}", serde_json::to_string_pretty(value)?),
+    }
+    Ok(())
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<usize>>();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<width$}", c, width = w))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!("{}", line(headers.to_vec()));
+    for row in rows {
+        println!("{}", line(row.iter().map(|c| c.as_str()).collect()));
+    }
+}
+
+#[cfg(test)]
+fn args(args: &[&str]) -> Result<Args, String> {
+    Args::parse(args.iter().map(|a| a.to_string()))
+}
+
+#[test]
+fn args_should_split_positionals_and_options() {
+    let parsed = args(&["kv", "set", "--tag", "a=1", "key", "--tag", "b=2", "value"]).unwrap();
+    assert_eq!(parsed.positionals, vec!["kv", "set", "key", "value"]);
+    assert_eq!(parsed.options["tag"], vec!["a=1", "b=2"]);
+    assert_eq!(parsed.option("tag"), Some("b=2"));
+    assert_eq!(parsed.positional(3, "value"), Ok("value"));
+    assert!(parsed.positional(4, "extra").is_err());
+
+    assert_eq!(
+        args(&["kv", "list", "--label"]).err().unwrap(),
+        "Missing value for --label"
+    );
+    assert_eq!(args(&["--help"]).err().unwrap(), "");
+}
+
+#[test]
+fn exit_codes_should_follow_the_http_status() {
+    let http = |status| -> Exception { HttpError::new(status, "https://sample.io/kv/a").into() };
+
+    assert_eq!(exit_code(&http(404)), EXIT_NOT_FOUND);
+    assert_eq!(exit_code(&http(401)), EXIT_AUTH);
+    assert_eq!(exit_code(&http(403)), EXIT_AUTH);
+    assert_eq!(exit_code(&http(500)), EXIT_ERROR);
+    assert_eq!(exit_code(&"invalid".into()), EXIT_ERROR);
+}
+
+#[test]
+fn format_options_should_come_from_the_flags() {
+    let (format, options) = format_options(
+        &args(&[
+            "export",
+            "-",
+            "--format",
+            "yaml",
+            "--separator",
+            ":",
+            "--label",
+            "web",
+        ])
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(format, Format::Yaml);
+    assert_eq!(options.separator.as_deref(), Some(":"));
+    assert_eq!(options.label.as_deref(), Some("web"));
+    assert_eq!(options.content_type, None);
+
+    assert!(format_options(&args(&["export", "-"]).unwrap()).is_err());
+    assert!(format_options(&args(&["export", "-", "--format", "xml"]).unwrap()).is_err());
+}
@@ -0,0 +1,234 @@
+//! Encrypted on-disk cache of listed key values, used as a fallback when the
+//! service is unreachable
+use crate::error::{HttpError, TimeoutError};
+use crate::model::{KeyValue, KeyValues};
+use crate::pipeline::new_call_id;
+use crate::Exception;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use isahc::error::ErrorKind;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const CACHE_EXTENSION: &str = "cache";
+
+/// Directory holding one encrypted file per endpoint and listing selector
+pub struct LocalCache {
+    dir: PathBuf,
+    cipher: Aes256Gcm,
+}
+
+/// Cache entry with the time it was written
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub key_values: KeyValues,
+    pub written_at: SystemTime,
+}
+
+/// Result of a listing that may have been served by the local cache
+#[derive(Debug)]
+pub struct Cached<T> {
+    pub value: T,
+    /// Time the cached value was written, `None` when the service answered
+    pub stale_since: Option<SystemTime>,
+}
+
+impl<T> Cached<T> {
+    pub fn is_stale(&self) -> bool {
+        self.stale_since.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    written_at: u64,
+    items: Vec<KeyValue>,
+}
+
+impl LocalCache {
+    /// Creates a cache in the given directory, which is created if missing
+    /// # Arguments
+    ///
+    /// * `dir` - Directory of the cache files
+    /// * `key` - 32 bytes AES-256 key used to encrypt the cache files
+    ///
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::cache::LocalCache;
+    ///
+    /// let key = base64::decode(&std::env::var("APPCONFIG_CACHE_KEY")?)?;
+    /// let cache = LocalCache::new("/var/cache/appconfig", &key)?;
+    /// ```
+    pub fn new<P: Into<PathBuf>>(dir: P, key: &[u8]) -> Result<LocalCache, Exception> {
+        if key.len() != KEY_LENGTH {
+            return Err(format!("Cache key must be {} bytes long", KEY_LENGTH).into());
+        }
+
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        Ok(LocalCache {
+            dir,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        })
+    }
+
+    /// Stores the key values listed for the selector of the endpoint, replacing the
+    /// file atomically
+    pub fn store(
+        &self,
+        endpoint: &str,
+        selector: &str,
+        key_values: &KeyValues,
+    ) -> Result<(), Exception> {
+        let file = CacheFile {
+            written_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            items: key_values.items.clone(),
+        };
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = self
+            .cipher
+            .encrypt(&nonce, serde_json::to_vec(&file)?.as_slice())
+            .map_err(|_| "Could not encrypt cache file")?;
+
+        // Unique so that concurrent writers never share a temporary file
+        let path = self.path(endpoint, selector);
+        let temp = path.with_extension(format!("{}.tmp", new_call_id()));
+        {
+            let mut out = fs::File::create(&temp)?;
+            out.write_all(&nonce)?;
+            out.write_all(&encrypted)?;
+            out.sync_all()?;
+        }
+        fs::rename(&temp, &path)?;
+
+        Ok(())
+    }
+
+    /// Loads the key values last stored for the selector of the endpoint, `None` when
+    /// there are none
+    pub fn load(&self, endpoint: &str, selector: &str) -> Result<Option<CacheEntry>, Exception> {
+        let contents = match fs::read(self.path(endpoint, selector)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if contents.len() < NONCE_LENGTH {
+            return Err("Cache file is corrupted".into());
+        }
+        let (nonce, encrypted) = contents.split_at(NONCE_LENGTH);
+        let decrypted = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| "Could not decrypt cache file, the key may have changed")?;
+
+        let file: CacheFile = serde_json::from_slice(&decrypted)?;
+        Ok(Some(CacheEntry {
+            key_values: KeyValues { items: file.items },
+            written_at: UNIX_EPOCH + Duration::from_secs(file.written_at),
+        }))
+    }
+
+    fn path(&self, endpoint: &str, selector: &str) -> PathBuf {
+        let hash = Sha256::digest(format!("{}\0{}", endpoint, selector).as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        self.dir.join(hash).with_extension(CACHE_EXTENSION)
+    }
+}
+
+/// Returns true for failures the cache can stand in for: connection and io errors,
+/// timeouts and server errors. Client errors such as 401 or 404, invalid requests
+/// and invalid responses are returned as is
+pub(crate) fn is_unavailable(e: &Exception) -> bool {
+    if let Some(h) = e.downcast_ref::<HttpError>() {
+        return h.status() >= 500;
+    }
+
+    match e.downcast_ref::<isahc::Error>().map(isahc::Error::kind) {
+        Some(ErrorKind::ConnectionFailed)
+        | Some(ErrorKind::NameResolution)
+        | Some(ErrorKind::Io)
+        | Some(ErrorKind::Timeout) => true,
+        Some(_) => false,
+        None => e.is::<TimeoutError>() || e.is::<std::io::Error>(),
+    }
+}
+
+#[cfg(test)]
+fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("appconfig-{}-{}", name, std::process::id()))
+}
+
+#[test]
+fn cache_should_round_trip_encrypted_key_values() {
+    let dir = temp_dir("round-trip");
+    let cache = LocalCache::new(&dir, &[7; KEY_LENGTH]).unwrap();
+    let key_values = KeyValues {
+        items: vec![KeyValue {
+            key: "app:port".to_owned(),
+            value: "8080".to_owned(),
+            label: Some("prod".to_owned()),
+            ..Default::default()
+        }],
+    };
+
+    let endpoint = "https://sample.azconfig.io";
+    assert!(cache.load(endpoint, "prod").unwrap().is_none());
+    cache.store(endpoint, "prod", &key_values).unwrap();
+
+    let raw = fs::read(cache.path(endpoint, "prod")).unwrap();
+    assert!(!String::from_utf8_lossy(&raw).contains("8080"));
+    assert!(cache
+        .load("https://other.azconfig.io", "prod")
+        .unwrap()
+        .is_none());
+
+    let entry = cache.load(endpoint, "prod").unwrap().unwrap();
+    assert_eq!(entry.key_values.items[0].key, "app:port");
+    assert_eq!(entry.key_values.items[0].label, Some("prod".to_owned()));
+    assert!(entry.written_at <= SystemTime::now());
+
+    let other = LocalCache::new(&dir, &[8; KEY_LENGTH]).unwrap();
+    assert!(other.load(endpoint, "prod").is_err());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn only_transport_and_server_errors_should_fall_back() {
+    assert!(is_unavailable(
+        &HttpError::new(503, "http://sample.io").into()
+    ));
+    assert!(!is_unavailable(
+        &HttpError::new(404, "http://sample.io").into()
+    ));
+    assert!(is_unavailable(
+        &std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into()
+    ));
+    assert!(is_unavailable(
+        &isahc::Error::from(ErrorKind::ConnectionFailed).into()
+    ));
+    assert!(is_unavailable(
+        &TimeoutError::new(
+            crate::error::TimeoutKind::Connect,
+            Duration::from_secs(1),
+            None
+        )
+        .into()
+    ));
+    assert!(!is_unavailable(
+        &isahc::Error::from(ErrorKind::InvalidCredentials).into()
+    ));
+    assert!(!is_unavailable(&"Invalid url".into()));
+}
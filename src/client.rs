@@ -1,23 +1,68 @@
+use crate::cache::{is_unavailable, Cached, LocalCache};
 use crate::endpoints::{EndpointUrl, Endpoints};
-use crate::error::HttpError;
-use crate::model::{KeyValue, KeyValues, Keys, Labels};
-use crate::request_sign::create_signed_request;
+use crate::error::{
+    HttpError, TimeoutError, TimeoutKind, NOT_FOUND_STATUS, NO_CONTENT_STATUS, THROTTLED_STATUS,
+};
+use crate::load_balancing::LoadBalancing;
+use crate::metrics::{operation_name, MetricsRecorder, NoopRecorder, RequestMetrics};
+use crate::model::{
+    CompositionType, CreateSnapshot, Key, KeyValue, KeyValues, Keys, Label, Labels, Operation,
+    OperationStatus, Page, PartialKeyValue, PartialKeyValues, SetKeyValueBody, Snapshot,
+    SnapshotFilter, SnapshotStatus, Snapshots, UpdateSnapshot,
+};
+use crate::pipeline::{
+    new_call_id, Headers, LoggingPolicy, PipelineRequest, PipelineResponse, Policy, PolicyContext,
+    TelemetryPolicy,
+};
+use crate::redact::REDACTED;
+use crate::request_sign::sign_request;
+use crate::response::Response;
 use crate::search_label::SearchLabel;
+use crate::select_field::{to_select_value, SelectField};
+use crate::sync_token::{SyncToken, SyncTokens, SYNC_TOKEN_HEADER};
+use crate::telemetry::{
+    in_attempt_span, record_field, record_response, record_retry, redact_url,
+    CORRELATION_REQUEST_ID_HEADER, REQUEST_ID_HEADER,
+};
+use crate::timeout::{deadline, effective, Timeouts};
+use crate::transport::{Transport, TransportConfig};
 use crate::Exception;
+use async_std::task;
 use http::Method;
+use isahc::config::Configurable;
+use isahc::error::ErrorKind;
+use isahc::AsyncReadResponseExt;
 use serde::de::DeserializeOwned;
-use std::str::FromStr;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use mime::Mime;
 use std::collections::HashMap;
 use url::Url;
+use zeroize::Zeroizing;
 
 const APP_CONFIG_MIME: &str = "application/vnd.microsoft.appconfig.kv+json";
+const SNAPSHOT_MIME: &str = "application/vnd.microsoft.appconfig.snapshot+json";
+const MERGE_PATCH_MIME: &str = "application/merge-patch+json";
+const CONTENT_TYPE_HEADER: &str = "content-type";
+const SNAPSHOT_API_VERSION: &str = "2023-10-01";
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest wait for a snapshot to be ready when the client has no total timeout
+const SNAPSHOT_MAX_WAIT: Duration = Duration::from_secs(600);
 
 pub struct AzureAppConfigClient {
     access_key: String,
-    secret: Vec<u8>,
+    /// Wiped from memory when the client is dropped
+    secret: Zeroizing<Vec<u8>>,
     endpoints: Endpoints,
+    cache: Option<LocalCache>,
+    timeouts: Timeouts,
+    transport: Transport,
+    metrics: Arc<dyn MetricsRecorder>,
+    policies: Vec<Arc<dyn Policy>>,
+    sync_tokens: SyncTokens,
+    /// Headers signed on top of the required ones, every header when `None`
+    signed_headers: Option<Vec<String>>,
 }
 
 impl AzureAppConfigClient {
@@ -38,12 +83,214 @@ impl AzureAppConfigClient {
     ///     "wgf9BDWeh/+Dtq8DmpsJSUpwrdgYLrXG8svE+VyM06w=");
     /// ```
     pub fn new<S: Into<String>>(uri_endpoint: S, access_key: S, secret: S) -> AzureAppConfigClient {
+        let secret = Zeroizing::new(secret.into());
         AzureAppConfigClient {
             access_key: access_key.into(),
-            secret: base64::decode(&secret.into()).expect("Could not decode secret key"),
+            secret: Zeroizing::new(base64::decode(&*secret).expect("Could not decode secret key")),
             endpoints: Endpoints::new(uri_endpoint.into()),
+            cache: None,
+            timeouts: Timeouts::default(),
+            transport: Transport::new(&TransportConfig::default())
+                .expect("Could not create the http transport"),
+            metrics: Arc::new(NoopRecorder),
+            policies: default_policies(),
+            sync_tokens: SyncTokens::default(),
+            signed_headers: None,
         }
     }
+
+    /// Creates a new instance of Azure App Configuration Client from a connection string
+    /// # Arguments
+    ///
+    /// * `connection_string` - Azure provided connection string, `Endpoint=...;Id=...;Secret=...`
+    ///
+    /// # Examples
+    /// ```
+    /// use azure_app_configuration::client::AzureAppConfigClient;
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(
+    ///     "Endpoint=https://yourendpoint.azconfig.io;Id=access_key;Secret=wgf9BDWeh/+Dtq8DmpsJSUpwrdgYLrXG8svE+VyM06w=")
+    ///     .unwrap();
+    /// assert_eq!(client.endpoint_uri(), "https://yourendpoint.azconfig.io");
+    /// ```
+    pub fn from_connection_string(
+        connection_string: &str,
+    ) -> Result<AzureAppConfigClient, Exception> {
+        let mut endpoint = None;
+        let mut id = None;
+        let mut secret = None;
+
+        for part in connection_string
+            .split(';')
+            .filter(|p| !p.trim().is_empty())
+        {
+            let mut pair = part.splitn(2, '=');
+            let name = pair.next().unwrap_or("").trim();
+            let value = pair.next().unwrap_or("").trim();
+
+            match name.to_lowercase().as_str() {
+                "endpoint" => endpoint = Some(value.trim_end_matches('/').to_string()),
+                "id" => id = Some(value.to_string()),
+                "secret" => secret = Some(Zeroizing::new(base64::decode(value)?)),
+                _ => {}
+            }
+        }
+
+        match (endpoint, id, secret) {
+            (Some(endpoint), Some(access_key), Some(secret)) => Ok(AzureAppConfigClient {
+                access_key,
+                secret,
+                endpoints: Endpoints::new(endpoint),
+                cache: None,
+                timeouts: Timeouts::default(),
+                transport: Transport::new(&TransportConfig::default())?,
+                metrics: Arc::new(NoopRecorder),
+                policies: default_policies(),
+                sync_tokens: SyncTokens::default(),
+                signed_headers: None,
+            }),
+            _ => Err("Connection string must contain Endpoint, Id and Secret".into()),
+        }
+    }
+
+    /// Keeps the last successful key value listing of every label in a local cache,
+    /// served instead when the service is unreachable or fails with a server error
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::cache::LocalCache;
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_cache(LocalCache::new("/var/cache/appconfig", &key)?);
+    /// ```
+    pub fn with_cache(mut self, cache: LocalCache) -> AzureAppConfigClient {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Configures the outbound proxy and TLS certificates of the http transport
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::transport::{ProxyConfig, TransportConfig};
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_transport(&TransportConfig {
+    ///         proxy: Some(ProxyConfig::new("http://proxy.corp.local:3128").with_credentials("user", "password")),
+    ///         no_proxy: vec![".corp.local".to_owned()],
+    ///         ..Default::default()
+    ///     })?;
+    /// ```
+    pub fn with_transport(
+        mut self,
+        config: &TransportConfig,
+    ) -> Result<AzureAppConfigClient, Exception> {
+        self.transport = Transport::new(config)?;
+        Ok(self)
+    }
+
+    /// Reports every http attempt to the metrics recorder
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::metrics::MetricsCrateRecorder;
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_metrics(MetricsCrateRecorder);
+    /// ```
+    pub fn with_metrics<M: MetricsRecorder + 'static>(
+        mut self,
+        recorder: M,
+    ) -> AzureAppConfigClient {
+        self.metrics = Arc::new(recorder);
+        self
+    }
+
+    /// Appends a policy to the request pipeline, which starts with the built-in
+    /// telemetry and logging policies
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::pipeline::{CorrelationIdPolicy, RetryPolicy};
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_policy(CorrelationIdPolicy::generated())
+    ///     .with_policy(RetryPolicy::default());
+    /// ```
+    pub fn with_policy<P: Policy + 'static>(mut self, policy: P) -> AzureAppConfigClient {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Signs only the given headers, when present, on top of the required `x-ms-date`,
+    /// `host` and `x-ms-content-sha256`. By default every header set before signing,
+    /// preconditions and policy headers included, is signed
+    /// # Examples
+    /// ```no run
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_signed_headers(vec!["If-Match", "If-None-Match"]);
+    /// ```
+    pub fn with_signed_headers<I, S>(mut self, headers: I) -> AzureAppConfigClient
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.signed_headers = Some(headers.into_iter().map(|h| h.into()).collect());
+        self
+    }
+
+    /// Replaces the whole request pipeline, built-in policies included
+    /// # Arguments
+    ///
+    /// * `policies` - Policies in the order they see requests, responses are
+    ///   seen in reverse order
+    pub fn with_pipeline(mut self, policies: Vec<Arc<dyn Policy>>) -> AzureAppConfigClient {
+        self.policies = policies;
+        self
+    }
+
+    /// Sets the time limits of every request, overriding the defaults of 10 seconds
+    /// to connect, 30 seconds to receive the response headers, 60 seconds to read the
+    /// body and 120 seconds in total. Use `timeout::with_timeout` or
+    /// `timeout::with_call_timeouts` to change them for a single call.
+    /// Timed out requests fail with a `TimeoutError`
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::timeout::Timeouts;
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_timeouts(Timeouts {
+    ///         connect: Some(Duration::from_secs(5)),
+    ///         ..Default::default()
+    ///     });
+    /// ```
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> AzureAppConfigClient {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Adds geo-replica endpoints, tried in order when the primary endpoint fails
+    /// with a transport error, a server error or throttling. Failed endpoints are
+    /// skipped for a backoff period, doubled on every consecutive failure.
+    /// Replicas are read only, writes always go to the primary endpoint
+    /// # Examples
+    /// ```no run
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_replicas(vec![
+    ///         "https://yourendpoint-westeurope.azconfig.io",
+    ///         "https://yourendpoint-eastus.azconfig.io",
+    ///     ]);
+    /// ```
+    pub fn with_replicas<I, S>(mut self, replicas: I) -> AzureAppConfigClient
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.endpoints = self.endpoints.with_replicas(
+            replicas
+                .into_iter()
+                .map(|r| r.into().trim_end_matches('/').to_string())
+                .collect(),
+        );
+        self
+    }
+
     /// List all available labels in Azure App Configuration service
     /// # Examples
     /// ```no run
@@ -53,9 +300,41 @@ impl AzureAppConfigClient {
     ///  }
     ///
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn list_labels(&self) -> Result<Labels, Exception> {
-        let url = &format!("{}", self.endpoints.get_uri(EndpointUrl::Labels)).parse::<Url>()?;
-        Ok(self.send_json(url, Method::GET, Body::empty()).await?)
+        Ok(self.list_labels_with_response().await?.into_value())
+    }
+
+    /// List all available labels along with the response metadata
+    /// # Examples
+    /// ```no run
+    /// let response = app_config_client.list_labels_with_response().await?;
+    /// println!("{} labels, request id {:?}", response.value().items.len(), response.request_id());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_labels_with_response(&self) -> Result<Response<Labels>, Exception> {
+        let url = &self.endpoints.url(EndpointUrl::Labels).build();
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// List every label, following the pagination of the service where `list_labels`
+    /// only returns the first page
+    /// # Examples
+    /// ```no run
+    /// let labels = app_config_client.list_all_labels().await?;
+    /// println!("{} labels", labels.items.len());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_all_labels(&self) -> Result<Labels, Exception> {
+        let mut items = Vec::new();
+        self.list_all(EndpointUrl::Labels, |page: Vec<Label>| {
+            items.extend(page);
+            Ok(())
+        })
+        .await?;
+
+        Ok(Labels { items })
     }
 
     /// List all available keys in Azure App Configuration service
@@ -66,9 +345,36 @@ impl AzureAppConfigClient {
     ///     println!("{:?}", k);
     /// }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn list_keys(&self) -> Result<Keys, Exception> {
-        let url = &format!("{}", self.endpoints.get_uri(EndpointUrl::Keys)).parse::<Url>()?;
-        Ok(self.send_json(url, Method::GET, Body::empty()).await?)
+        Ok(self.list_keys_with_response().await?.into_value())
+    }
+
+    /// List all available keys along with the response metadata
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_keys_with_response(&self) -> Result<Response<Keys>, Exception> {
+        let url = &self.endpoints.url(EndpointUrl::Keys).build();
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// List every key, following the pagination of the service where `list_keys`
+    /// only returns the first page
+    /// # Examples
+    /// ```no run
+    /// let keys = app_config_client.list_all_keys().await?;
+    /// println!("{} keys", keys.items.len());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_all_keys(&self) -> Result<Keys, Exception> {
+        let mut items = Vec::new();
+        self.list_all(EndpointUrl::Keys, |page: Vec<Key>| {
+            items.extend(page);
+            Ok(())
+        })
+        .await?;
+
+        Ok(Keys { items })
     }
 
     /// List all available key values in Azure App Configuration service
@@ -79,18 +385,171 @@ impl AzureAppConfigClient {
     ///     println!("{:?}", k);
     ///  }
     /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
     pub async fn list_key_values(&self, label: SearchLabel<'_>) -> Result<KeyValues, Exception> {
-        let url = &format!(
-            "{}?label={}",
-            self.endpoints.get_uri(EndpointUrl::KeyValues),
-            label.to_string()
-        )
-        .parse::<Url>()?;
+        Ok(self.list_key_values_cached(label).await?.value)
+    }
+
+    /// List key values falling back to the local cache, when configured, if the
+    /// service is unavailable. `stale_since` tells when the cached listing was written
+    /// # Examples
+    /// ```no run
+    /// let key_values = app_config_client.list_key_values_cached(SearchLabel::All).await?;
+    /// if let Some(written_at) = key_values.stale_since {
+    ///     println!("Service unavailable, using key values cached at {:?}", written_at);
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
+    pub async fn list_key_values_cached(
+        &self,
+        label: SearchLabel<'_>,
+    ) -> Result<Cached<KeyValues>, Exception> {
+        let selector = label.to_string();
+        let result = self
+            .list_key_values_with_response(label)
+            .await
+            .map(Response::into_value);
+
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                return result.map(|value| Cached {
+                    value,
+                    stale_since: None,
+                })
+            }
+        };
+
+        match result {
+            Ok(value) => {
+                if let Err(e) = cache.store(&self.endpoints.base_endpoint(), &selector, &value) {
+                    log::warn!("Could not write the local cache: {}", e);
+                }
+                Ok(Cached {
+                    value,
+                    stale_since: None,
+                })
+            }
+            Err(e) if is_unavailable(&e) => {
+                match cache.load(&self.endpoints.base_endpoint(), &selector) {
+                    Ok(Some(entry)) => {
+                        log::warn!("Serving cached key values after error: {}", e);
+                        Ok(Cached {
+                            value: entry.key_values,
+                            stale_since: Some(entry.written_at),
+                        })
+                    }
+                    Ok(None) => Err(e),
+                    // The service error tells why the call failed, not the cache one
+                    Err(cache_error) => {
+                        log::warn!("Could not read the local cache: {}", cache_error);
+                        Err(e)
+                    }
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List key values along with the response metadata, bypassing the local cache
+    /// # Examples
+    /// ```no run
+    /// let response = app_config_client.list_key_values_with_response(SearchLabel::All).await?;
+    /// println!("Served by {} with sync token {:?}", response.endpoint(), response.sync_token());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
+    pub async fn list_key_values_with_response(
+        &self,
+        label: SearchLabel<'_>,
+    ) -> Result<Response<KeyValues>, Exception> {
+        let url = &self
+            .endpoints
+            .url(EndpointUrl::KeyValues)
+            .query("label", &label.to_string())
+            .build();
+
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// List every key value with the label, following the pagination of the service
+    /// where `list_key_values` only returns the first page
+    /// # Examples
+    /// ```no run
+    /// let key_values = app_config_client
+    ///     .list_all_key_values(SearchLabel::For("Production"))
+    ///     .await?;
+    /// println!("{} key values", key_values.items.len());
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
+    pub async fn list_all_key_values(
+        &self,
+        label: SearchLabel<'_>,
+    ) -> Result<KeyValues, Exception> {
+        let url = self
+            .endpoints
+            .url(EndpointUrl::KeyValues)
+            .query("label", &label.to_string())
+            .build();
+
+        let mut items = Vec::new();
+        self.list_pages(EndpointUrl::KeyValues, url, |page: Vec<KeyValue>| {
+            items.extend(page);
+            Ok(())
+        })
+        .await?;
+
+        Ok(KeyValues { items })
+    }
+
+    /// List key values retrieving only the selected fields
+    /// # Arguments
+    /// * `label` - Label filter for the listed key values
+    /// * `fields` - Fields to be retrieved, the rest will be `None`. Every field is retrieved when empty
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::select_field::SelectField;
+    ///
+    /// let key_values = app_config_client
+    ///     .list_key_values_select(SearchLabel::All, &[SelectField::Key, SelectField::Etag])
+    ///     .await
+    ///     .unwrap();
+    ///  for k in key_values.items {
+    ///     println!("{:?} {:?}", k.key, k.etag);
+    ///  }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
+    pub async fn list_key_values_select(
+        &self,
+        label: SearchLabel<'_>,
+        fields: &[SelectField],
+    ) -> Result<PartialKeyValues, Exception> {
+        Ok(self
+            .list_key_values_select_with_response(label, fields)
+            .await?
+            .into_value())
+    }
+
+    /// List key values retrieving only the selected fields, along with the response metadata
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(label = %label)))]
+    pub async fn list_key_values_select_with_response(
+        &self,
+        label: SearchLabel<'_>,
+        fields: &[SelectField],
+    ) -> Result<Response<PartialKeyValues>, Exception> {
+        let url = &self
+            .endpoints
+            .url(EndpointUrl::KeyValues)
+            .query("label", &label.to_string())
+            .optional_query("$select", to_select_value(fields).as_deref())
+            .build();
 
-        Ok(self.send_json(url, Method::GET, Body::empty()).await?)
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
     }
 
-    /// Set the target key with the desired value, label, tags and content-type
+    /// Set the target key with the desired value, label, tags and content-type.
+    /// `set_key_request` offers the same with fluent setters, preconditions and locking
     /// # Arguments
     /// * `key` - Key name to be set
     /// * `value` - Key value
@@ -118,6 +577,10 @@ impl AzureAppConfigClient {
     ///        println!("{:?}", kv);
     ///
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
     pub async fn set_key<'a, S: Into<String>>(
         &self,
         key: S,
@@ -126,34 +589,101 @@ impl AzureAppConfigClient {
         tags: Option<HashMap<S, S>>,
         content_type: Option<S>,
     ) -> Result<KeyValue, Exception> {
-        let mut k = KeyValue::default();
+        let key = key.into();
+        record_field("key", &key);
+        let key_value = new_key_value(value, tags, content_type);
+
+        Ok(self
+            .put_key_value(key, label, &key_value, None)
+            .await?
+            .into_value())
+    }
+
+    /// Set the target key like `set_key`, returning the response metadata too
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn set_key_with_response<'a, S: Into<String>>(
+        &self,
+        key: S,
+        value: S,
+        label: SearchLabel<'a>,
+        tags: Option<HashMap<S, S>>,
+        content_type: Option<S>,
+    ) -> Result<Response<KeyValue>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        let key_value = new_key_value(value, tags, content_type);
 
-        k.value = value.into();
+        self.put_key_value(key, label, &key_value, None).await
+    }
 
-        k.content_type = match content_type {
-            Some(c) => Some(c.into()),
-            None => None,
+    /// Lists every item of the endpoint, of every key and label for key values and
+    /// revisions, following pagination and calling `on_page` with the items of each page
+    pub(crate) async fn list_all<T, F>(
+        &self,
+        endpoint: EndpointUrl,
+        on_page: F,
+    ) -> Result<(), Exception>
+    where
+        T: DeserializeOwned,
+        F: FnMut(Vec<T>) -> Result<(), Exception>,
+    {
+        let builder = self.endpoints.url(endpoint);
+        let url = match endpoint {
+            EndpointUrl::KeyValues | EndpointUrl::Revisions => {
+                builder.query("key", "*").query("label", "*").build()
+            }
+            _ => builder.build(),
         };
 
-        if let Some(tg) = tags {
-            for (ky, v) in tg {
-                k.tags.insert(ky.into(), v.into());
+        self.list_pages(endpoint, url, on_page).await
+    }
+
+    /// Follows the `@nextLink` of every page from the url, calling `on_page` with the
+    /// items of each page
+    async fn list_pages<T, F>(
+        &self,
+        endpoint: EndpointUrl,
+        mut url: Url,
+        mut on_page: F,
+    ) -> Result<(), Exception>
+    where
+        T: DeserializeOwned,
+        F: FnMut(Vec<T>) -> Result<(), Exception>,
+    {
+        let base = self.endpoints.get_uri(endpoint);
+
+        loop {
+            let page: Page<T> = self.send_json(&url, Method::GET, Body::empty()).await?;
+            on_page(page.items)?;
+
+            match page.next_link {
+                Some(link) => url = base.join(&link)?,
+                None => return Ok(()),
             }
         }
+    }
 
-        let json = serde_json::to_string(&k)?;
+    /// Sets the key value, failing with a 412 status when the precondition is not met
+    pub(crate) async fn put_key_value<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+        key_value: &KeyValue,
+        precondition: Option<&Precondition>,
+    ) -> Result<Response<KeyValue>, Exception> {
+        let json = serde_json::to_string(&SetKeyValueBody::from(key_value))?;
+        let url = &get_key_value_url(self, key, label)?;
 
-        let url = &format!(
-            "{}/{key}?label={lbl}",
-            self.endpoints.get_uri(EndpointUrl::KeyValues),
-            key = key.into(),
-            lbl = label.to_string()
+        self.send_json_with_headers(
+            url,
+            Method::PUT,
+            Body::from(json.into_bytes()),
+            &precondition_headers(precondition),
         )
-        .parse::<Url>()?;
-
-        Ok(self
-            .send_json(url, Method::PUT, Body::from(json.into_bytes()))
-            .await?)
+        .await
     }
 
     /// Get key value
@@ -168,122 +698,1041 @@ impl AzureAppConfigClient {
     ///    .get_key_value("ConnectionString", SearchLabel::For("ContosoApp"))
     ///    .await;
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
     pub async fn get_key_value<S: Into<String>>(
         &self,
         key: S,
         label: SearchLabel<'_>,
     ) -> Result<KeyValue, Exception> {
-        let url = &format!(
-            "{host}/{key}?label={label}",
-            host = self.endpoints.get_uri(EndpointUrl::KeyValues),
-            key = key.into(),
-            label = label.to_string(),
-        )
-        .parse::<Url>()?;
+        let key = key.into();
+        record_field("key", &key);
+        Ok(self
+            .get_key_value_with_response(key, label)
+            .await?
+            .into_value())
+    }
 
+    /// Get key value along with the response metadata, such as its ETag
+    /// # Examples
+    /// ```no run
+    /// let response = app_config_client
+    ///    .get_key_value_with_response("ConnectionString", SearchLabel::For("ContosoApp"))
+    ///    .await?;
+    /// println!("ETag {:?}, last modified {:?}", response.etag(), response.last_modified());
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn get_key_value_with_response<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<Response<KeyValue>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        let url = &get_key_value_url(self, key, label)?;
+
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// Get key value retrieving only the selected fields
+    /// # Arguments
+    /// * `key` - Key name to be retrieved
+    /// * `label` - Key label (SearchLabel::All for no label and SearchLabel::For("label") to stablish label
+    /// * `fields` - Fields to be retrieved, the rest will be `None`. Every field is retrieved when empty
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::search_label::SearchLabel;
+    /// use azure_app_configuration::select_field::SelectField;
+    ///
+    /// let kv = app_config_client
+    ///    .get_key_value_select(
+    ///        "ConnectionString",
+    ///        SearchLabel::For("ContosoApp"),
+    ///        &[SelectField::Key, SelectField::Etag],
+    ///    )
+    ///    .await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn get_key_value_select<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+        fields: &[SelectField],
+    ) -> Result<PartialKeyValue, Exception> {
+        let key = key.into();
+        record_field("key", &key);
         Ok(self
-            .send_json::<KeyValue>(url, Method::GET, Body::empty())
-            .await?)
+            .get_key_value_select_with_response(key, label, fields)
+            .await?
+            .into_value())
+    }
+
+    /// Get key value retrieving only the selected fields, along with the response metadata
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn get_key_value_select_with_response<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+        fields: &[SelectField],
+    ) -> Result<Response<PartialKeyValue>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        let url = &self
+            .endpoints
+            .url(EndpointUrl::KeyValues)
+            .segment(&key)?
+            .query("label", &label.to_string())
+            .optional_query("$select", to_select_value(fields).as_deref())
+            .build();
+
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
     }
 
-    /// Remove target key value from Azure App Configuration service
+    /// Remove target key value from Azure App Configuration service, returning the
+    /// removed key value or `None` when it did not exist
     /// # Arguments
     /// * `key` - Key to be deleted
     /// * `label` - Label where the key will be found and removed, if no label is specified all labels with that key will be remove
     /// # Examples
     /// ```no run
-    /// app_config_client
+    /// let removed = app_config_client
     ///    .remove_key_value("EnableProxy", SearchLabel::For("ApplicationLabel"))
     ///    .await
-    ///    .unwrap();    ///
+    ///    .unwrap();
+    /// if let Some(kv) = removed {
+    ///     println!("Removed {:?}", kv);
+    /// }
     /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
     pub async fn remove_key_value<S: Into<String>>(
         &self,
         key: S,
         label: SearchLabel<'_>,
-    ) -> Result<(), Exception> {
-        let url = get_key_value_url(self, key, label)?;
-
-        self.send_request(&url, Method::DELETE, Body::empty())
-            .await?;
-
-        Ok(())
+    ) -> Result<Option<KeyValue>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        Ok(self
+            .remove_key_value_with_response(key, label)
+            .await?
+            .into_value())
     }
 
-    async fn send_request(
+    /// Remove target key value, failing with a 404 `HttpError` when it did not exist
+    /// # Examples
+    /// ```no run
+    /// let removed = app_config_client
+    ///    .remove_existing_key_value("EnableProxy", SearchLabel::For("ApplicationLabel"))
+    ///    .await?;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn remove_existing_key_value<S: Into<String>>(
         &self,
-        url: &Url,
-        method: Method,
-        body: Body,
-    ) -> Result<String, Exception> {
-        log::debug!(
-            "Sending {} request to {}",
-            &method.to_string(),
-            &url.to_string()
-        );
-
-        let mut req = create_signed_request(
-            self.access_key.clone(),
-            self.secret.clone(),
-            url,
-            body,
-            method.clone(),
-        )
-        .await?;
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<KeyValue, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        let url = get_key_value_url(self, key.as_str(), label)?;
 
-        if method != Method::GET {
-            req = req.set_mime(Mime::from_str(APP_CONFIG_MIME).unwrap());
+        match self.remove_key_value(key, label).await? {
+            Some(kv) => Ok(kv),
+            None => Err(HttpError::new(NOT_FOUND_STATUS, url.as_str()).into()),
         }
+    }
 
-        let mut result = req.await?;
-        let content = result.body_string().await?;
-
-        match result.status() {
-            v if !v.is_success() => Err(HttpError::new(v.as_u16() as usize, url.as_str()).into()),
-            _ => Ok(content),
-        }
+    /// Remove target key value, returning the response metadata
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn remove_key_value_with_response<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<Response<Option<KeyValue>>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        self.delete_key_value(key, label, None).await
     }
-    async fn send_json<T: DeserializeOwned>(
+
+    /// Removes the key value, failing with a 412 status when the precondition is not met.
+    /// The service answers with the removed key value, or with a 204 status and no
+    /// body when it did not exist
+    pub(crate) async fn delete_key_value<S: Into<String>>(
         &self,
-        url: &Url,
-        method: Method,
-        body: Body,
-    ) -> Result<T, Exception> {
-        let result = self.send_request(url, method, body).await?;
+        key: S,
+        label: SearchLabel<'_>,
+        precondition: Option<&Precondition>,
+    ) -> Result<Response<Option<KeyValue>>, Exception> {
+        let url = get_key_value_url(self, key, label)?;
 
-        log::debug!("JSON: {}", result);
+        let response = self
+            .send_request_with_headers(
+                &url,
+                Method::DELETE,
+                Body::empty(),
+                &precondition_headers(precondition),
+            )
+            .await?;
 
-        Ok(serde_json::from_str::<T>(&result)?)
+        let removed = match usize::from(response.status()) {
+            NO_CONTENT_STATUS => None,
+            _ if response.value().trim().is_empty() => None,
+            _ => Some(serde_json::from_str::<KeyValue>(response.value())?),
+        };
+        Ok(response.map(|_| removed))
     }
 
-    pub fn endpoint_uri(&self) -> String {
-        self.endpoints.base_endpoint()
+    /// Lock a key value, making it read only
+    /// # Arguments
+    /// * `key` - Key to be locked
+    /// * `label` - Key label (SearchLabel::All for no label and SearchLabel::For("label") to stablish label
+    /// # Examples
+    /// ```no run
+    /// let kv = app_config_client
+    ///    .lock_key_value("EnableProxy", SearchLabel::For("ApplicationLabel"))
+    ///    .await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn lock_key_value<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<KeyValue, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        Ok(self
+            .lock_key_value_with_response(key, label)
+            .await?
+            .into_value())
     }
-}
-
-fn get_key_value_url<S: Into<String>>(
-    client: &AzureAppConfigClient,
-    key: S,
-    label: SearchLabel,
-) -> Result<Url, Exception> {
-    format!(
-        "{host}/{key}?label={label}",
-        host = client.endpoints.get_uri(EndpointUrl::KeyValues),
-        key = key.into(),
-        label = label.to_string(),
-    )
-    .parse::<Url>()
-    .map_err(|e| e.into())
-}
 
-#[derive(Debug, Clone)]
-pub(crate) struct Body {
-    contents: Vec<u8>,
-}
+    /// Lock a key value, returning the response metadata too
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn lock_key_value_with_response<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<Response<KeyValue>, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        self.put_lock(key, label, None).await
+    }
+
+    /// Locks the key value, failing with a 412 status when the precondition is not met
+    pub(crate) async fn put_lock<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+        precondition: Option<&Precondition>,
+    ) -> Result<Response<KeyValue>, Exception> {
+        let url = &get_lock_url(self, key, label)?;
+        self.send_json_with_headers(
+            url,
+            Method::PUT,
+            Body::empty(),
+            &precondition_headers(precondition),
+        )
+        .await
+    }
+
+    /// Unlock a key value, allowing it to be modified again
+    /// # Arguments
+    /// * `key` - Key to be unlocked
+    /// * `label` - Key label (SearchLabel::All for no label and SearchLabel::For("label") to stablish label
+    /// # Examples
+    /// ```no run
+    /// let kv = app_config_client
+    ///    .unlock_key_value("EnableProxy", SearchLabel::For("ApplicationLabel"))
+    ///    .await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(key = tracing::field::Empty, label = %label))
+    )]
+    pub async fn unlock_key_value<S: Into<String>>(
+        &self,
+        key: S,
+        label: SearchLabel<'_>,
+    ) -> Result<KeyValue, Exception> {
+        let key = key.into();
+        record_field("key", &key);
+        let url = &get_lock_url(self, key, label)?;
+        self.send_json(url, Method::DELETE, Body::empty()).await
+    }
+
+    /// Create a snapshot with the key values matching the filters and wait until it is ready.
+    /// The creation is polled, as often as the service `Retry-After` asks, within the total
+    /// timeout of the client or of `with_call_timeouts`, or 10 minutes without one, after
+    /// which a `TimeoutError` of kind `Total` is returned while the snapshot may still be created
+    /// # Arguments
+    /// * `name` - Snapshot name
+    /// * `filters` - Key and label filters selecting the key values of the snapshot
+    /// * `composition_type` - Whether key values are composed by key or by key and label
+    /// * `retention_period` - How long the snapshot is kept once archived, service default if `None`
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::model::{CompositionType, SnapshotFilter};
+    ///
+    /// let snapshot = app_config_client
+    ///     .create_snapshot(
+    ///         "release-1.0",
+    ///         &[SnapshotFilter::new("app/*", Some("prod"))],
+    ///         CompositionType::Key,
+    ///         None,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn create_snapshot<S: Into<String>>(
+        &self,
+        name: S,
+        filters: &[SnapshotFilter],
+        composition_type: CompositionType,
+        retention_period: Option<Duration>,
+    ) -> Result<Snapshot, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        let snapshot = CreateSnapshot {
+            filters: filters.to_vec(),
+            composition_type,
+            retention_period: retention_period.map(|r| r.as_secs()),
+        };
+        let json = serde_json::to_string(&snapshot)?;
+
+        let url = &get_snapshot_url(self, &name)?;
+        self.send_request(
+            url,
+            Method::PUT,
+            Body::from(json.into_bytes()).with_mime(SNAPSHOT_MIME),
+        )
+        .await?;
+
+        let operation_url = &self
+            .endpoints
+            .url(EndpointUrl::Operations)
+            .query("snapshot", &name)
+            .query("api-version", SNAPSHOT_API_VERSION)
+            .build();
+
+        let limit = effective(&self.timeouts).total.unwrap_or(SNAPSHOT_MAX_WAIT);
+        deadline(
+            Some(limit),
+            TimeoutKind::Total,
+            Some(operation_url.as_str()),
+            // Boxed as the nested request futures are too large for the stack of a task
+            Box::pin(async {
+                loop {
+                    let response = self
+                        .send_json_with_headers::<Operation>(
+                            operation_url,
+                            Method::GET,
+                            Body::empty(),
+                            &[],
+                        )
+                        .await?;
+                    let delay = response.retry_after().unwrap_or(SNAPSHOT_POLL_INTERVAL);
+                    let operation = response.into_value();
+
+                    match operation.status {
+                        OperationStatus::Succeeded => return Ok(()),
+                        OperationStatus::Failed | OperationStatus::Canceled => {
+                            let message = operation
+                                .error
+                                .and_then(|e| e.message.or(e.code))
+                                .unwrap_or_default();
+                            return Err(format!(
+                                "Snapshot {} creation {:?}: {}",
+                                name, operation.status, message
+                            )
+                            .into());
+                        }
+                        _ => task::sleep(delay).await,
+                    }
+                }
+            }),
+        )
+        .await?;
+
+        self.get_snapshot(name).await
+    }
+
+    /// List all snapshots in Azure App Configuration service
+    /// # Examples
+    /// ```no run
+    /// let snapshots = app_config_client.list_snapshots().await.unwrap();
+    /// for s in snapshots.items {
+    ///     println!("{:?}", s);
+    /// }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_snapshots(&self) -> Result<Snapshots, Exception> {
+        Ok(self.list_snapshots_with_response().await?.into_value())
+    }
+
+    /// List all snapshots along with the response metadata
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn list_snapshots_with_response(&self) -> Result<Response<Snapshots>, Exception> {
+        let url = &self
+            .endpoints
+            .url(EndpointUrl::Snapshots)
+            .query("api-version", SNAPSHOT_API_VERSION)
+            .build();
+
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// Get snapshot
+    /// # Arguments
+    /// * `name` - Snapshot name
+    /// # Examples
+    /// ```no run
+    /// let snapshot = app_config_client.get_snapshot("release-1.0").await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn get_snapshot<S: Into<String>>(&self, name: S) -> Result<Snapshot, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        Ok(self.get_snapshot_with_response(name).await?.into_value())
+    }
+
+    /// Get snapshot along with the response metadata, such as its ETag
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn get_snapshot_with_response<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<Response<Snapshot>, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        let url = &get_snapshot_url(self, &name)?;
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    /// Archive a ready snapshot, it will be deleted once its retention period expires
+    /// # Arguments
+    /// * `name` - Snapshot name
+    /// # Examples
+    /// ```no run
+    /// let snapshot = app_config_client.archive_snapshot("release-1.0").await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn archive_snapshot<S: Into<String>>(&self, name: S) -> Result<Snapshot, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        Ok(self
+            .update_snapshot_status(&name, SnapshotStatus::Archived)
+            .await?
+            .into_value())
+    }
+
+    /// Archive a ready snapshot like `archive_snapshot`, returning the response metadata too
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn archive_snapshot_with_response<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<Response<Snapshot>, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        self.update_snapshot_status(&name, SnapshotStatus::Archived)
+            .await
+    }
+
+    /// Recover an archived snapshot, making it ready again
+    /// # Arguments
+    /// * `name` - Snapshot name
+    /// # Examples
+    /// ```no run
+    /// let snapshot = app_config_client.recover_snapshot("release-1.0").await;
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn recover_snapshot<S: Into<String>>(&self, name: S) -> Result<Snapshot, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        Ok(self
+            .update_snapshot_status(&name, SnapshotStatus::Ready)
+            .await?
+            .into_value())
+    }
+
+    /// Recover an archived snapshot like `recover_snapshot`, returning the response metadata too
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn recover_snapshot_with_response<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<Response<Snapshot>, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        self.update_snapshot_status(&name, SnapshotStatus::Ready)
+            .await
+    }
+
+    /// List the key values stored in a snapshot
+    /// # Arguments
+    /// * `name` - Snapshot name
+    /// # Examples
+    /// ```no run
+    /// let key_values = app_config_client
+    ///     .list_snapshot_key_values("release-1.0")
+    ///     .await
+    ///     .unwrap();
+    /// for k in key_values.items {
+    ///     println!("{:?}", k);
+    /// }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn list_snapshot_key_values<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<KeyValues, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        Ok(self
+            .list_snapshot_key_values_with_response(name)
+            .await?
+            .into_value())
+    }
+
+    /// List the key values stored in a snapshot along with the response metadata
+    /// # Arguments
+    /// * `name` - Snapshot name
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(snapshot = tracing::field::Empty))
+    )]
+    pub async fn list_snapshot_key_values_with_response<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<Response<KeyValues>, Exception> {
+        let name = name.into();
+        record_field("snapshot", &name);
+        let url = &self
+            .endpoints
+            .url(EndpointUrl::KeyValues)
+            .query("snapshot", &name)
+            .query("api-version", SNAPSHOT_API_VERSION)
+            .build();
+
+        self.send_json_with_headers(url, Method::GET, Body::empty(), &[])
+            .await
+    }
+
+    async fn update_snapshot_status(
+        &self,
+        name: &str,
+        status: SnapshotStatus,
+    ) -> Result<Response<Snapshot>, Exception> {
+        let json = serde_json::to_string(&UpdateSnapshot { status })?;
+        let url = &get_snapshot_url(self, name)?;
+
+        self.send_json_with_headers(
+            url,
+            Method::PATCH,
+            Body::from(json.into_bytes()).with_mime(MERGE_PATCH_MIME),
+            &[],
+        )
+        .await
+    }
+
+    async fn send_request(
+        &self,
+        url: &Url,
+        method: Method,
+        body: Body,
+    ) -> Result<Response<String>, Exception> {
+        self.send_request_with_headers(url, method, body, &[]).await
+    }
+
+    /// Sends the request with additional headers, which are covered by the signature,
+    /// failing over to the replica endpoints when the service is unavailable
+    async fn send_request_with_headers(
+        &self,
+        url: &Url,
+        method: Method,
+        body: Body,
+        headers: &[(&'static str, String)],
+    ) -> Result<Response<String>, Exception> {
+        deadline(
+            effective(&self.timeouts).total,
+            TimeoutKind::Total,
+            Some(url.as_str()),
+            self.send_with_failover(url, method, body, headers),
+        )
+        .await
+    }
+
+    async fn send_with_failover(
+        &self,
+        url: &Url,
+        method: Method,
+        body: Body,
+        headers: &[(&'static str, String)],
+    ) -> Result<Response<String>, Exception> {
+        let mut last_error = None;
+        let call_id = new_call_id();
+
+        let candidates = self.endpoints.candidates(method == Method::GET);
+        for (attempt, index) in candidates.into_iter().enumerate() {
+            let endpoint_url = self.endpoints.rebase(url, index)?;
+            let started = Instant::now();
+            let context = PolicyContext {
+                call_id: call_id.clone(),
+                attempt,
+                retry: 0,
+            };
+
+            match in_attempt_span(
+                &method,
+                &endpoint_url,
+                attempt,
+                self.send_to_endpoint(&endpoint_url, method.clone(), &body, headers, context),
+            )
+            .await
+            {
+                Err(e) if should_fail_over(&e) => {
+                    log::warn!(
+                        "Request to {} failed, trying next endpoint: {}",
+                        redact_url(&endpoint_url),
+                        e
+                    );
+                    self.endpoints.mark_failed(index);
+                    last_error = Some(e);
+                }
+                result => {
+                    self.endpoints.mark_healthy(index, started.elapsed());
+                    let endpoint = self.endpoints.all()[index].to_owned();
+                    return result.map(|response| Response::new(response, endpoint));
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| "No endpoint available".into()))
+    }
+
+    /// Sends the request to an endpoint through the pipeline, retrying it as long
+    /// as a policy asks to and reporting every round to the metrics recorder
+    async fn send_to_endpoint(
+        &self,
+        url: &Url,
+        method: Method,
+        body: &Body,
+        headers: &[(&'static str, String)],
+        mut context: PolicyContext,
+    ) -> Result<PipelineResponse, Exception> {
+        let operation = operation_name(&method, url);
+        let mut request = PipelineRequest {
+            method,
+            url: url.clone(),
+            headers: Headers::default(),
+            body: body.value(),
+        };
+        if request.method != Method::GET {
+            request.headers.set(CONTENT_TYPE_HEADER, body.mime());
+        }
+        for (name, value) in headers {
+            request.headers.set(*name, value.as_str());
+        }
+
+        loop {
+            // Retries send back the tokens received with the responses they follow
+            match self.sync_tokens.header_value() {
+                Some(tokens) => request.headers.set(SYNC_TOKEN_HEADER, tokens),
+                None => request.headers.remove(SYNC_TOKEN_HEADER),
+            }
+
+            let started = Instant::now();
+            let result = self.run_pipeline(&context, request.clone()).await;
+
+            self.metrics.request(&RequestMetrics {
+                operation: &operation,
+                status: result.as_ref().ok().map(|(response, _)| response.status),
+                latency: started.elapsed(),
+                attempt: context.attempt,
+                retry: context.retry,
+                bytes_sent: body.len(),
+                bytes_received: result
+                    .as_ref()
+                    .map_or(0, |(response, _)| response.body.len()),
+            });
+
+            let (response, retry_after) = result?;
+            for tokens in response.headers.get_all(SYNC_TOKEN_HEADER) {
+                self.sync_tokens.update_from_header(tokens);
+            }
+
+            match (response, retry_after) {
+                (response, Some(delay)) => {
+                    log::debug!(
+                        "Retrying request to {} after {:?}, status was {}",
+                        redact_url(url),
+                        delay,
+                        response.status
+                    );
+                    task::sleep(delay).await;
+                    context.retry += 1;
+                    record_retry(context.retry);
+                }
+                (response, None) => {
+                    record_response(
+                        response.status,
+                        response.headers.get(REQUEST_ID_HEADER),
+                        response.headers.get(CORRELATION_REQUEST_ID_HEADER),
+                    );
+
+                    return if (200..300).contains(&response.status) {
+                        Ok(response)
+                    } else {
+                        Err(HttpError::new(response.status as usize, url.as_str())
+                            .with_headers(response.headers)
+                            .into())
+                    };
+                }
+            }
+        }
+    }
+
+    /// Runs the request through the policies, signing and sending it unless a
+    /// policy answers it, and returns the response with the retry delay asked by
+    /// the policies if any
+    async fn run_pipeline(
+        &self,
+        context: &PolicyContext,
+        mut request: PipelineRequest,
+    ) -> Result<(PipelineResponse, Option<Duration>), Exception> {
+        let mut answered = None;
+        let mut ran = 0;
+        for policy in &self.policies {
+            ran += 1;
+            if let Some(response) = policy.on_request(context, &mut request)? {
+                answered = Some(response);
+                break;
+            }
+        }
+
+        let mut response = match answered {
+            Some(response) => response,
+            None => {
+                sign_request(
+                    &self.access_key,
+                    &self.secret,
+                    self.signed_headers.as_deref(),
+                    &mut request,
+                )?;
+                for policy in &self.policies {
+                    policy.on_signed_request(context, &mut request)?;
+                }
+                self.exchange(&request).await?
+            }
+        };
+
+        let mut retry_after = None;
+        for policy in self.policies[..ran].iter().rev() {
+            let delay = policy.on_response(context, &request, &mut response)?;
+            retry_after = retry_after.max(delay);
+        }
+
+        Ok((response, retry_after))
+    }
+
+    /// Sends the signed request and reads the response, whatever its status
+    async fn exchange(&self, request: &PipelineRequest) -> Result<PipelineResponse, Exception> {
+        let url = Some(request.url.as_str());
+        let timeouts = effective(&self.timeouts);
+
+        let mut builder = isahc::Request::builder()
+            .method(request.method.as_str())
+            .uri(request.url.as_str());
+        if let Some(connect) = timeouts.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        for (name, value) in request.headers.iter() {
+            builder = builder.header(name, value);
+        }
+        let req = builder.body(request.body.clone())?;
+
+        let client = self.transport.client_for(&request.url);
+        let mut response = deadline(timeouts.headers, TimeoutKind::Headers, url, async {
+            client
+                .send(req)
+                .await
+                .map_err(|e| connect_error(e, timeouts.connect, url))
+        })
+        .await?;
+
+        let content = deadline(timeouts.body_read, TimeoutKind::BodyRead, url, async {
+            Ok(response.bytes().await?)
+        })
+        .await?;
+
+        let mut headers = Headers::default();
+        for (name, value) in response.headers().iter() {
+            if let Ok(value) = value.to_str() {
+                headers.append(name.as_str(), value);
+            }
+        }
+
+        Ok(PipelineResponse {
+            status: response.status().as_u16(),
+            headers,
+            body: String::from_utf8(content)?,
+        })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        url: &Url,
+        method: Method,
+        body: Body,
+    ) -> Result<T, Exception> {
+        Ok(self
+            .send_json_with_headers(url, method, body, &[])
+            .await?
+            .into_value())
+    }
+
+    async fn send_json_with_headers<T: DeserializeOwned>(
+        &self,
+        url: &Url,
+        method: Method,
+        body: Body,
+        headers: &[(&'static str, String)],
+    ) -> Result<Response<T>, Exception> {
+        let response = self
+            .send_request_with_headers(url, method, body, headers)
+            .await?;
+
+        // Bodies hold key values, which may be secrets
+        log::debug!("Received json body of {} bytes", response.value().len());
+
+        let value = serde_json::from_str::<T>(response.value())?;
+        Ok(response.map(|_| value))
+    }
+
+    pub fn endpoint_uri(&self) -> String {
+        self.endpoints.base_endpoint()
+    }
+
+    /// Spreads read requests across the healthy endpoints, see `with_replicas`
+    /// # Examples
+    /// ```no run
+    /// use azure_app_configuration::load_balancing::LoadBalancing;
+    ///
+    /// let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+    ///     .with_replicas(vec!["https://yourendpoint-eastus.azconfig.io"])
+    ///     .with_load_balancing(LoadBalancing::RoundRobin);
+    /// ```
+    pub fn with_load_balancing(mut self, load_balancing: LoadBalancing) -> AzureAppConfigClient {
+        self.endpoints = self.endpoints.with_load_balancing(load_balancing);
+        self
+    }
+
+    /// Endpoint, primary or replica, that answered the latest successful request
+    pub fn last_endpoint(&self) -> Option<String> {
+        self.endpoints.last_served()
+    }
+
+    /// Adds a sync token, such as the one of an Event Grid push notification, so
+    /// later reads from any replica see the change it comes from
+    /// # Arguments
+    ///
+    /// * `token` - Token in the `<id>=<value>;sn=<sequence number>` format
+    ///
+    /// # Examples
+    /// ```no run
+    /// client.update_sync_token(&event.data.sync_token)?;
+    /// let kv = client.get_key_value("ConnectionString", SearchLabel::All).await?;
+    /// ```
+    pub fn update_sync_token(&self, token: &str) -> Result<(), Exception> {
+        self.sync_tokens.update(token.parse::<SyncToken>()?);
+        Ok(())
+    }
+
+    /// Latest sync token of every id, sent along every request
+    pub fn sync_tokens(&self) -> Vec<SyncToken> {
+        self.sync_tokens.all()
+    }
+}
+
+/// The secret is never printed
+impl fmt::Debug for AzureAppConfigClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AzureAppConfigClient")
+            .field("endpoint", &self.endpoints.base_endpoint())
+            .field("access_key", &self.access_key)
+            .field("secret", &REDACTED)
+            .field("timeouts", &self.timeouts)
+            .finish()
+    }
+}
+
+/// ETag condition for writes, guarding against concurrent modifications
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Precondition {
+    /// The key value must exist with the given etag
+    IfMatch(String),
+    /// The key value must not exist
+    IfNoneMatch,
+}
+
+/// Pipeline of new clients, retries are left to the endpoint failover
+fn default_policies() -> Vec<Arc<dyn Policy>> {
+    vec![
+        Arc::new(TelemetryPolicy::default()),
+        Arc::new(LoggingPolicy),
+    ]
+}
+
+/// Reports curl timeouts, which can only come from the connect limit, as `TimeoutError`
+fn connect_error(e: isahc::Error, limit: Option<Duration>, url: Option<&str>) -> Exception {
+    match limit {
+        Some(limit) if *e.kind() == ErrorKind::Timeout => {
+            TimeoutError::new(TimeoutKind::Connect, limit, url).into()
+        }
+        _ => e.into(),
+    }
+}
+
+/// Transport errors, server errors and throttling are retried on the next endpoint
+fn should_fail_over(e: &Exception) -> bool {
+    match e.downcast_ref::<HttpError>().map(|h| h.status()) {
+        Some(status) => status == THROTTLED_STATUS || status >= 500,
+        None => true,
+    }
+}
+
+fn precondition_headers(precondition: Option<&Precondition>) -> Vec<(&'static str, String)> {
+    match precondition {
+        Some(Precondition::IfMatch(etag)) => vec![("If-Match", format!("\"{}\"", etag))],
+        Some(Precondition::IfNoneMatch) => vec![("If-None-Match", "*".to_owned())],
+        None => vec![],
+    }
+}
+
+fn new_key_value<S: Into<String>>(
+    value: S,
+    tags: Option<HashMap<S, S>>,
+    content_type: Option<S>,
+) -> KeyValue {
+    let mut k = KeyValue {
+        value: value.into(),
+        content_type: content_type.map(|c| c.into()),
+        ..Default::default()
+    };
+
+    if let Some(tg) = tags {
+        for (ky, v) in tg {
+            k.tags.insert(ky.into(), v.into());
+        }
+    }
+
+    k
+}
+
+fn get_key_value_url<S: Into<String>>(
+    client: &AzureAppConfigClient,
+    key: S,
+    label: SearchLabel,
+) -> Result<Url, Exception> {
+    Ok(client
+        .endpoints
+        .url(EndpointUrl::KeyValues)
+        .segment(&key.into())?
+        .query("label", &label.to_string())
+        .build())
+}
+
+fn get_lock_url<S: Into<String>>(
+    client: &AzureAppConfigClient,
+    key: S,
+    label: SearchLabel,
+) -> Result<Url, Exception> {
+    Ok(client
+        .endpoints
+        .url(EndpointUrl::Locks)
+        .segment(&key.into())?
+        .query("label", &label.to_string())
+        .build())
+}
+
+fn get_snapshot_url(client: &AzureAppConfigClient, name: &str) -> Result<Url, Exception> {
+    Ok(client
+        .endpoints
+        .url(EndpointUrl::Snapshots)
+        .segment(name)?
+        .query("api-version", SNAPSHOT_API_VERSION)
+        .build())
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Body {
+    contents: Vec<u8>,
+    mime: &'static str,
+}
 
 impl Body {
     fn empty() -> Self {
-        Body { contents: vec![] }
+        Body::from(vec![])
+    }
+
+    /// Sets the content type sent along the body, key value json by default
+    fn with_mime(mut self, mime: &'static str) -> Self {
+        self.mime = mime;
+        self
+    }
+
+    fn mime(&self) -> &'static str {
+        self.mime
     }
 
     pub(crate) fn value(&self) -> Vec<u8> {
@@ -298,6 +1747,7 @@ impl From<Vec<u8>> for Body {
     fn from(bytes: Vec<u8>) -> Self {
         Body {
             contents: bytes.to_vec(),
+            mime: APP_CONFIG_MIME,
         }
     }
 }
@@ -308,9 +1758,9 @@ impl From<&str> for Body {
     }
 }
 
-impl ToString for Body {
-    fn to_string(&self) -> String {
-        String::from_utf8_lossy(&self.contents).to_string()
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.contents))
     }
 }
 
@@ -326,3 +1776,681 @@ fn body_len_fn_should_return_proper_body_length() {
     let b = Body::from(bytes.to_vec());
     assert_eq!(b.len(), 8);
 }
+
+#[test]
+fn precondition_headers_should_quote_etags() {
+    assert_eq!(
+        precondition_headers(Some(&Precondition::IfMatch("abc".to_owned()))),
+        vec![("If-Match", "\"abc\"".to_owned())]
+    );
+    assert_eq!(
+        precondition_headers(Some(&Precondition::IfNoneMatch)),
+        vec![("If-None-Match", "*".to_owned())]
+    );
+    assert!(precondition_headers(None).is_empty());
+}
+
+/// Answers a single request with the given json body, returning the listening
+/// endpoint and a handle to the received request
+#[cfg(test)]
+fn serve_once(body: &'static str) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 4096];
+        let read = stream.read(&mut request).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        String::from_utf8_lossy(&request[..read]).into_owned()
+    });
+
+    (endpoint, handle)
+}
+
+#[test]
+fn requests_should_fail_over_to_replicas() {
+    let (replica, _) = serve_once("{\"items\":[]}");
+
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0")
+        .with_replicas(vec![replica.as_str()]);
+    let labels = task::block_on(client.list_labels()).unwrap();
+
+    assert!(labels.items.is_empty());
+    assert_eq!(client.last_endpoint(), Some(replica));
+    assert_eq!(client.endpoints.candidates(true), vec![1, 0]);
+}
+
+#[test]
+fn requests_should_go_through_the_proxy() {
+    use crate::transport::ProxyConfig;
+
+    let (proxy, request) = serve_once("{\"items\":[]}");
+
+    let client = AzureAppConfigClient::new("http://store.invalid", "id", "c2VjcmV0")
+        .with_transport(&TransportConfig {
+            proxy: Some(ProxyConfig::new(proxy)),
+            ..Default::default()
+        })
+        .unwrap();
+    task::block_on(client.list_labels()).unwrap();
+
+    let request = request.join().unwrap();
+    assert!(request.starts_with("GET http://store.invalid/labels HTTP/1.1"));
+    assert!(request.to_lowercase().contains("host: store.invalid"));
+}
+
+#[test]
+fn no_proxy_hosts_should_be_reached_directly() {
+    use crate::transport::ProxyConfig;
+
+    let (endpoint, request) = serve_once("{\"items\":[]}");
+
+    // The proxy is unreachable, the request only succeeds when it is skipped
+    let client = AzureAppConfigClient::new(endpoint.as_str(), "id", "c2VjcmV0")
+        .with_transport(&TransportConfig {
+            proxy: Some(ProxyConfig::new("http://127.0.0.1:9")),
+            no_proxy: vec!["127.0.0.1".to_owned()],
+            ..Default::default()
+        })
+        .unwrap();
+    task::block_on(client.list_labels()).unwrap();
+
+    assert!(request.join().unwrap().starts_with("GET /labels HTTP/1.1"));
+}
+
+#[test]
+fn hung_requests_should_time_out() {
+    use crate::error::TimeoutError;
+
+    // Accepts connections but never answers
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+    let client =
+        AzureAppConfigClient::new(endpoint.as_str(), "id", "c2VjcmV0").with_timeouts(Timeouts {
+            headers: Some(Duration::from_millis(200)),
+            ..Timeouts::none()
+        });
+    let error = task::block_on(client.list_labels()).unwrap_err();
+
+    let timeout = error.downcast_ref::<TimeoutError>().unwrap();
+    assert_eq!(timeout.kind(), TimeoutKind::Headers);
+    drop(listener);
+}
+
+#[test]
+fn calls_should_override_every_limit_of_the_client() {
+    use crate::error::TimeoutError;
+    use crate::timeout::{with_call_timeouts, with_timeout};
+
+    // Accepts connections but never answers
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let client =
+        AzureAppConfigClient::new(endpoint.as_str(), "id", "c2VjcmV0").with_timeouts(Timeouts {
+            headers: Some(Duration::from_millis(300)),
+            total: Some(Duration::from_millis(100)),
+            ..Timeouts::none()
+        });
+    let kind = |error: Exception| error.downcast_ref::<TimeoutError>().unwrap().kind();
+
+    let error = task::block_on(client.list_labels()).unwrap_err();
+    assert_eq!(kind(error), TimeoutKind::Total);
+
+    // A longer total lets the headers limit of the client fire first
+    let error =
+        task::block_on(with_timeout(Duration::from_secs(5), client.list_labels())).unwrap_err();
+    assert_eq!(kind(error), TimeoutKind::Headers);
+
+    let error = task::block_on(with_call_timeouts(
+        Timeouts {
+            headers: Some(Duration::from_millis(50)),
+            ..Timeouts::none()
+        },
+        client.list_labels(),
+    ))
+    .unwrap_err();
+    let timeout = error.downcast_ref::<TimeoutError>().unwrap();
+    assert_eq!(timeout.kind(), TimeoutKind::Headers);
+    assert_eq!(timeout.after(), Duration::from_millis(50));
+    drop(listener);
+}
+
+#[test]
+fn curl_timeouts_should_be_connect_timeouts() {
+    use crate::error::TimeoutError;
+
+    let error = connect_error(
+        ErrorKind::Timeout.into(),
+        Some(Duration::from_secs(10)),
+        Some("https://sample.io/kv"),
+    );
+    let timeout = error.downcast_ref::<TimeoutError>().unwrap();
+    assert_eq!(timeout.kind(), TimeoutKind::Connect);
+    assert_eq!(timeout.after(), Duration::from_secs(10));
+
+    let error = connect_error(
+        ErrorKind::ConnectionFailed.into(),
+        Some(Duration::from_secs(10)),
+        None,
+    );
+    assert!(error.downcast_ref::<isahc::Error>().is_some());
+}
+
+#[test]
+fn client_debug_should_not_print_the_secret() {
+    let client = AzureAppConfigClient::new("http://sample.io", "id", "c2VjcmV0");
+    let debug = format!("{:?}", client);
+
+    assert!(debug.contains("id"));
+    assert!(!debug.contains("c2VjcmV0"));
+    assert!(!debug.contains("115, 101, 99"));
+}
+
+#[test]
+fn attempts_should_be_reported_to_the_metrics_recorder() {
+    use crate::metrics::RequestMetrics;
+    use std::sync::Mutex;
+
+    /// Operation, status, endpoint attempt, retry and bytes received of every attempt
+    type Attempt = (String, Option<u16>, usize, u32, usize);
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Attempt>>);
+
+    impl MetricsRecorder for Arc<Recorder> {
+        fn request(&self, request: &RequestMetrics) {
+            self.0.lock().unwrap().push((
+                request.operation.to_owned(),
+                request.status,
+                request.attempt,
+                request.retry,
+                request.bytes_received,
+            ));
+        }
+    }
+
+    let (replica, _) = serve_once("{\"items\":[]}");
+    let recorder = Arc::new(Recorder::default());
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0")
+        .with_replicas(vec![replica.as_str()])
+        .with_metrics(recorder.clone());
+    task::block_on(client.list_labels()).unwrap();
+
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        vec![
+            ("GET labels".to_owned(), None, 0, 0, 0),
+            ("GET labels".to_owned(), Some(200), 1, 0, 12),
+        ]
+    );
+}
+
+#[test]
+fn policies_should_short_circuit_and_retry_requests() {
+    use crate::pipeline::RetryPolicy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with a 503 status until the third request
+    struct Unavailable(AtomicUsize);
+
+    impl Policy for Unavailable {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            Ok(Some(match self.0.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => PipelineResponse::new(503, ""),
+                _ => PipelineResponse::new(200, "{\"items\":[]}"),
+            }))
+        }
+    }
+
+    let unavailable = Arc::new(Unavailable(AtomicUsize::new(0)));
+    let client =
+        AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0").with_pipeline(vec![
+            Arc::new(RetryPolicy {
+                delay: Duration::from_millis(1),
+                ..Default::default()
+            }),
+            unavailable.clone(),
+        ]);
+    let labels = task::block_on(client.list_labels()).unwrap();
+
+    assert!(labels.items.is_empty());
+    assert_eq!(unavailable.0.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn headers_set_by_policies_should_be_signed() {
+    use crate::pipeline::{CorrelationIdPolicy, UserAgentPolicy};
+
+    let (endpoint, request) = serve_once("{\"items\":[]}");
+    let client = AzureAppConfigClient::new(endpoint.as_str(), "id", "c2VjcmV0")
+        .with_policy(UserAgentPolicy::new("sample/1.0"))
+        .with_policy(CorrelationIdPolicy::new("call-1"));
+    task::block_on(client.list_labels()).unwrap();
+
+    let request = request.join().unwrap().to_lowercase();
+    assert!(request.contains("user-agent: sample/1.0\r\n"));
+    assert!(request.contains("x-ms-correlation-request-id: call-1\r\n"));
+    assert!(request.contains(
+        "signedheaders=x-ms-date;host;x-ms-content-sha256;user-agent;x-ms-correlation-request-id&"
+    ));
+}
+
+#[test]
+fn sync_tokens_should_be_sent_back() {
+    use std::sync::Mutex;
+
+    /// Records the sync tokens it receives and answers with a newer one
+    #[derive(Default)]
+    struct Replica(Mutex<Vec<Option<String>>>);
+
+    impl Policy for Replica {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let mut received = self.0.lock().unwrap();
+            received.push(request.headers.get(SYNC_TOKEN_HEADER).map(String::from));
+
+            let mut response = PipelineResponse::new(200, "{\"items\":[]}");
+            let token = format!("jtqGc1I4=MDoyOA==;sn={}", received.len() + 10);
+            response.headers.set(SYNC_TOKEN_HEADER, token);
+            Ok(Some(response))
+        }
+    }
+
+    let replica = Arc::new(Replica::default());
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0")
+        .with_pipeline(vec![replica.clone()]);
+    client.update_sync_token("pushed=ZXZlbnQ=;sn=1").unwrap();
+
+    task::block_on(client.list_labels()).unwrap();
+    task::block_on(client.list_labels()).unwrap();
+
+    assert_eq!(
+        *replica.0.lock().unwrap(),
+        vec![
+            Some("pushed=ZXZlbnQ=".to_owned()),
+            Some("jtqGc1I4=MDoyOA==,pushed=ZXZlbnQ=".to_owned()),
+        ]
+    );
+    assert_eq!(client.sync_tokens()[0].sequence_number(), 12);
+    assert!(client.update_sync_token("invalid").is_err());
+}
+
+#[test]
+fn retries_should_send_the_sync_tokens_of_the_failed_attempts() {
+    use crate::pipeline::RetryPolicy;
+    use std::sync::Mutex;
+
+    /// Throttles the first request, answering every request with two tokens
+    #[derive(Default)]
+    struct Throttling(Mutex<Vec<Option<String>>>);
+
+    impl Policy for Throttling {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let mut received = self.0.lock().unwrap();
+            received.push(request.headers.get(SYNC_TOKEN_HEADER).map(String::from));
+
+            let status = if received.len() == 1 { 429 } else { 200 };
+            let mut response = PipelineResponse::new(status, "{\"items\":[]}");
+            response.headers.set("retry-after-ms", "1");
+            let sn = received.len();
+            response
+                .headers
+                .append(SYNC_TOKEN_HEADER, format!("a=YQ==;sn={}", sn));
+            response
+                .headers
+                .append(SYNC_TOKEN_HEADER, format!("b=Yg==;sn={}", sn));
+            Ok(Some(response))
+        }
+    }
+
+    let throttling = Arc::new(Throttling::default());
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(RetryPolicy::default()), throttling.clone()]);
+
+    task::block_on(client.list_labels()).unwrap();
+
+    assert_eq!(
+        *throttling.0.lock().unwrap(),
+        vec![None, Some("a=YQ==,b=Yg==".to_owned())]
+    );
+    let tokens = client.sync_tokens();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens.iter().all(|token| token.sequence_number() == 2));
+}
+
+#[test]
+fn responses_should_carry_the_metadata() {
+    /// Answers with a key value and its metadata
+    struct KeyValueWithEtag;
+
+    impl Policy for KeyValueWithEtag {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let mut response = PipelineResponse::new(
+                200,
+                r#"{"etag":"4f6dd610","key":"UseCache","label":null,"value":"true","content_type":null,"last_modified":"2017-12-05T02:41:26+00:00","locked":false,"tags":{}}"#,
+            );
+            response.headers.set("ETag", "\"4f6dd610\"");
+            response
+                .headers
+                .set("Last-Modified", "Tue, 05 Dec 2017 02:41:26 GMT");
+            response.headers.set("x-ms-request-id", "7a1b");
+            Ok(Some(response))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(KeyValueWithEtag)]);
+    let response =
+        task::block_on(client.get_key_value_with_response("UseCache", SearchLabel::All)).unwrap();
+
+    assert_eq!(response.value().value, "true");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.etag(), Some("\"4f6dd610\""));
+    assert_eq!(
+        response.last_modified(),
+        Some("Tue, 05 Dec 2017 02:41:26 GMT")
+    );
+    assert_eq!(response.request_id(), Some("7a1b"));
+    assert_eq!(response.endpoint(), "https://sample.azconfig.io");
+}
+
+#[test]
+fn snapshot_and_select_responses_should_carry_the_metadata() {
+    /// Answers with a snapshot, archived when patched, or a key value and an ETag
+    struct SnapshotWithEtag;
+
+    impl Policy for SnapshotWithEtag {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let body = if request.url.path().starts_with("/snapshots") {
+                match request.method {
+                    Method::PATCH => {
+                        r#"{"name":"release","status":"archived","filters":[{"key":"app:*"}],"composition_type":"key"}"#
+                    }
+                    _ => {
+                        r#"{"name":"release","status":"ready","filters":[{"key":"app:*"}],"composition_type":"key"}"#
+                    }
+                }
+            } else {
+                r#"{"key":"UseCache","etag":"4f6dd610"}"#
+            };
+            let mut response = PipelineResponse::new(200, body);
+            response.headers.set("ETag", "\"4f6dd610\"");
+            Ok(Some(response))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(SnapshotWithEtag)]);
+
+    let response = task::block_on(client.get_snapshot_with_response("release")).unwrap();
+    assert_eq!(response.value().status, SnapshotStatus::Ready);
+    assert_eq!(response.etag(), Some("\"4f6dd610\""));
+
+    let response = task::block_on(client.archive_snapshot_with_response("release")).unwrap();
+    assert_eq!(response.value().status, SnapshotStatus::Archived);
+    assert_eq!(response.etag(), Some("\"4f6dd610\""));
+
+    let response = task::block_on(client.recover_snapshot_with_response("release")).unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = task::block_on(client.get_key_value_select_with_response(
+        "UseCache",
+        SearchLabel::All,
+        &[SelectField::Key, SelectField::Etag],
+    ))
+    .unwrap();
+    assert_eq!(response.value().key.as_deref(), Some("UseCache"));
+    assert_eq!(response.etag(), Some("\"4f6dd610\""));
+}
+
+#[test]
+fn unreadable_caches_should_not_hide_the_service_error() {
+    /// Answers every request as unavailable
+    struct Unavailable;
+
+    impl Policy for Unavailable {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            Ok(Some(PipelineResponse::new(503, "")))
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("appconfig-unreadable-{}", std::process::id()));
+    let endpoint = "https://sample.azconfig.io";
+    LocalCache::new(&dir, &[7; 32])
+        .unwrap()
+        .store(endpoint, "", &KeyValues { items: vec![] })
+        .unwrap();
+
+    // Written with another key, the cache file cannot be decrypted
+    let client = AzureAppConfigClient::new(endpoint, "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(Unavailable)])
+        .with_cache(LocalCache::new(&dir, &[8; 32]).unwrap());
+    let error = task::block_on(client.list_key_values_cached(SearchLabel::All)).unwrap_err();
+
+    assert_eq!(error.downcast_ref::<HttpError>().unwrap().status(), 503);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn failed_responses_should_carry_their_headers() {
+    /// Fails every request with the request id of the service
+    struct Forbidden;
+
+    impl Policy for Forbidden {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let mut response = PipelineResponse::new(403, "");
+            response.headers.set("x-ms-request-id", "9c2e");
+            Ok(Some(response))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(Forbidden)]);
+
+    let error = task::block_on(client.list_snapshots_with_response()).unwrap_err();
+    let error = error.downcast_ref::<HttpError>().unwrap();
+    assert_eq!(error.status(), 403);
+    assert_eq!(error.request_id(), Some("9c2e"));
+    assert!(error.to_string().ends_with("request id: 9c2e"));
+}
+
+#[test]
+fn removals_should_return_the_removed_key_value() {
+    /// Answers with the removed key value, then with a 204 status as it is gone
+    #[derive(Default)]
+    struct RemoveOnce(std::sync::atomic::AtomicBool);
+
+    impl Policy for RemoveOnce {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            _request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            Ok(Some(
+                if self.0.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    PipelineResponse::new(204, "")
+                } else {
+                    PipelineResponse::new(
+                        200,
+                        r#"{"etag":"4f6dd610","key":"UseCache","label":null,"value":"true","content_type":null,"last_modified":"2017-12-05T02:41:26+00:00","locked":false,"tags":{}}"#,
+                    )
+                },
+            ))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(RemoveOnce::default())]);
+
+    let removed = task::block_on(client.remove_key_value("UseCache", SearchLabel::All)).unwrap();
+    assert_eq!(removed.unwrap().value, "true");
+
+    let removed = task::block_on(client.remove_key_value("UseCache", SearchLabel::All)).unwrap();
+    assert!(removed.is_none());
+
+    let error =
+        task::block_on(client.remove_existing_key_value("UseCache", SearchLabel::All)).unwrap_err();
+    assert_eq!(error.downcast_ref::<HttpError>().unwrap().status(), 404);
+}
+
+#[test]
+fn snapshot_creation_should_stop_polling_at_the_total_timeout() {
+    use crate::error::TimeoutError;
+    use crate::model::{CompositionType, SnapshotFilter};
+
+    /// Accepts the snapshot and then reports it as running forever
+    struct Running;
+
+    impl Policy for Running {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let mut response = PipelineResponse::new(200, r#"{"status":"Running"}"#);
+            if request.method == Method::GET {
+                response.headers.set("retry-after-ms", "10");
+            }
+            Ok(Some(response))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(Running)])
+        .with_timeouts(Timeouts {
+            total: Some(Duration::from_millis(200)),
+            ..Timeouts::none()
+        });
+
+    let error = task::block_on(client.create_snapshot(
+        "release",
+        &[SnapshotFilter::new("app/*", None)],
+        CompositionType::Key,
+        None,
+    ))
+    .unwrap_err();
+
+    let timeout = error.downcast_ref::<TimeoutError>().unwrap();
+    assert_eq!(timeout.kind(), TimeoutKind::Total);
+    assert!(timeout.url().unwrap().contains("/operations"));
+}
+
+#[test]
+fn label_and_key_listings_should_follow_every_page() {
+    /// Serves labels and keys over two pages
+    struct TwoPages;
+
+    impl Policy for TwoPages {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let path = request.url.path().to_owned();
+            let query = request.url.query().unwrap_or_default();
+            assert!(!query.contains("key=") && !query.contains("label="));
+            Ok(Some(match (path.as_str(), query.contains("after=")) {
+                ("/labels", false) => PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"name":"dev"}],"@nextLink":"/labels?after=ZGV2"}"#,
+                ),
+                ("/labels", true) => PipelineResponse::new(200, r#"{"items":[{"name":"prod"}]}"#),
+                (_, false) => PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"name":"a"}],"@nextLink":"/keys?after=YQ%3D%3D"}"#,
+                ),
+                (_, true) => PipelineResponse::new(200, r#"{"items":[{"name":"b"}]}"#),
+            }))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(TwoPages)]);
+
+    let labels = task::block_on(client.list_all_labels()).unwrap();
+    let labels = labels
+        .items
+        .iter()
+        .filter_map(|l| l.name())
+        .collect::<Vec<&str>>();
+    assert_eq!(labels, vec!["dev", "prod"]);
+
+    let keys = task::block_on(client.list_all_keys()).unwrap();
+    let keys = keys.items.iter().map(|k| k.name()).collect::<Vec<&str>>();
+    assert_eq!(keys, vec!["a", "b"]);
+}
+
+#[test]
+fn listings_should_follow_every_page() {
+    /// Answers the first page with a link to the second one
+    struct TwoPages;
+
+    impl Policy for TwoPages {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            let query = request.url.query().unwrap_or_default();
+            Ok(Some(if query.contains("after=") {
+                assert!(query.contains("label=web"));
+                PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"key":"b","value":"2","label":"web","content_type":null}]}"#,
+                )
+            } else {
+                PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"key":"a","value":"1","label":"web","content_type":null}],"@nextLink":"/kv?after=YQ%3D%3D&label=web"}"#,
+                )
+            }))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(TwoPages)]);
+
+    let key_values = task::block_on(client.list_all_key_values(SearchLabel::For("web"))).unwrap();
+    let keys = key_values
+        .items
+        .iter()
+        .map(|kv| kv.key.as_str())
+        .collect::<Vec<&str>>();
+    assert_eq!(keys, vec!["a", "b"]);
+}
@@ -0,0 +1,293 @@
+use crate::client::AzureAppConfigClient;
+use crate::formats::{self, Format, FormatOptions};
+#[cfg(test)]
+use crate::model::kv;
+use crate::model::KeyValue;
+use crate::search_label::SearchLabel;
+use crate::telemetry::record_field;
+use crate::Exception;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Set of key values to be compared, key values are matched by key so every
+/// selector must resolve to a single label, comparing fails on duplicate keys
+pub enum Selector<'a> {
+    /// Key values of a store with the given label
+    Store {
+        client: &'a AzureAppConfigClient,
+        label: SearchLabel<'a>,
+    },
+    /// Key values already loaded, for instance from a local file
+    KeyValues(Vec<KeyValue>),
+}
+
+impl<'a> Selector<'a> {
+    /// Loads the key values of a local file in the given format
+    pub fn file<P: AsRef<std::path::Path>>(
+        path: P,
+        format: Format,
+        options: &FormatOptions,
+    ) -> Result<Selector<'a>, Exception> {
+        let input = std::fs::read_to_string(path)?;
+        Ok(Selector::KeyValues(formats::import(
+            &input, format, options,
+        )?))
+    }
+
+    /// Records the label of a store selector on the current span
+    fn record_label(&self, field: &'static str) {
+        if let Selector::Store { label, .. } = self {
+            record_field(field, &label.to_string());
+        }
+    }
+
+    async fn key_values(self) -> Result<Vec<KeyValue>, Exception> {
+        match self {
+            Selector::Store { client, label } => Ok(client.list_all_key_values(label).await?.items),
+            Selector::KeyValues(key_values) => Ok(key_values),
+        }
+    }
+}
+
+/// Change needed for the target to match the source
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// Key only present in the source
+    Added(KeyValue),
+    /// Key only present in the target
+    Removed(KeyValue),
+    /// Key present in both with a different value, content type or tags
+    Modified { source: KeyValue, target: KeyValue },
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added(kv) | Change::Removed(kv) => &kv.key,
+            Change::Modified { source, .. } => &source.key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Diff {
+    /// Changes ordered by key
+    pub changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Renders the changes as text, `+` for added, `-` for removed
+    /// and `~` for modified keys followed by their changed fields
+    pub fn to_unified(&self) -> String {
+        let mut text = String::new();
+
+        for change in &self.changes {
+            match change {
+                Change::Added(kv) => text.push_str(&format!("+ {} = {:?}\n", kv.key, kv.value)),
+                Change::Removed(kv) => text.push_str(&format!("- {} = {:?}\n", kv.key, kv.value)),
+                Change::Modified { source, target } => {
+                    text.push_str(&format!("~ {}\n", source.key));
+                    for field in changed_fields(source, target) {
+                        let (old, new) = match field {
+                            "value" => (json!(target.value), json!(source.value)),
+                            "content_type" => {
+                                (json!(target.content_type), json!(source.content_type))
+                            }
+                            _ => (sorted_tags(&target.tags), sorted_tags(&source.tags)),
+                        };
+                        text.push_str(&format!("-   {} = {}\n", field, old));
+                        text.push_str(&format!("+   {} = {}\n", field, new));
+                    }
+                }
+            }
+        }
+
+        text
+    }
+
+    /// Renders the changes as a json array
+    pub fn to_json(&self) -> Result<String, Exception> {
+        let changes = self
+            .changes
+            .iter()
+            .map(|change| match change {
+                Change::Added(kv) => json!({
+                    "change": "added",
+                    "key": kv.key,
+                    "source": entry(kv),
+                }),
+                Change::Removed(kv) => json!({
+                    "change": "removed",
+                    "key": kv.key,
+                    "target": entry(kv),
+                }),
+                Change::Modified { source, target } => json!({
+                    "change": "modified",
+                    "key": source.key,
+                    "fields": changed_fields(source, target),
+                    "source": entry(source),
+                    "target": entry(target),
+                }),
+            })
+            .collect::<Vec<Value>>();
+
+        Ok(serde_json::to_string_pretty(&changes)?)
+    }
+}
+
+/// Compares two selectors, returning the changes needed for `target` to match `source`
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::diff::{diff, Selector};
+///
+/// let changes = diff(
+///     Selector::Store { client: &app_config_client, label: SearchLabel::For("staging") },
+///     Selector::Store { client: &app_config_client, label: SearchLabel::For("prod") },
+/// )
+/// .await
+/// .unwrap();
+/// print!("{}", changes.to_unified());
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(source_label = tracing::field::Empty, target_label = tracing::field::Empty)
+    )
+)]
+pub async fn diff(source: Selector<'_>, target: Selector<'_>) -> Result<Diff, Exception> {
+    source.record_label("source_label");
+    target.record_label("target_label");
+    let source = source.key_values().await?;
+    let target = target.key_values().await?;
+
+    diff_key_values(&source, &target)
+}
+
+/// Compares two sets of key values by key, returning the changes needed for
+/// `target` to match `source`. Fails when a key appears more than once in a set,
+/// as key values of several labels cannot be matched
+pub fn diff_key_values(source: &[KeyValue], target: &[KeyValue]) -> Result<Diff, Exception> {
+    let source = by_key(source)?;
+    let mut target = by_key(target)?;
+    let mut changes = Vec::new();
+
+    for (key, s) in source {
+        match target.remove(key) {
+            None => changes.push(Change::Added(s.clone())),
+            Some(t) if !changed_fields(s, t).is_empty() => changes.push(Change::Modified {
+                source: s.clone(),
+                target: t.clone(),
+            }),
+            _ => {}
+        }
+    }
+    changes.extend(target.values().map(|t| Change::Removed((*t).clone())));
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+
+    Ok(Diff { changes })
+}
+
+fn by_key(key_values: &[KeyValue]) -> Result<BTreeMap<&str, &KeyValue>, Exception> {
+    let mut keys = BTreeMap::new();
+    for kv in key_values {
+        if keys.insert(kv.key.as_str(), kv).is_some() {
+            return Err(format!(
+                "Key {} appears more than once, select a single label to compare",
+                kv.key
+            )
+            .into());
+        }
+    }
+    Ok(keys)
+}
+
+fn changed_fields(source: &KeyValue, target: &KeyValue) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if source.value != target.value {
+        fields.push("value");
+    }
+    if source.content_type != target.content_type {
+        fields.push("content_type");
+    }
+    if source.tags != target.tags {
+        fields.push("tags");
+    }
+    fields
+}
+
+fn sorted_tags(tags: &HashMap<String, String>) -> Value {
+    json!(tags.iter().collect::<BTreeMap<&String, &String>>())
+}
+
+fn entry(kv: &KeyValue) -> Value {
+    json!({
+        "label": kv.label,
+        "value": kv.value,
+        "content_type": kv.content_type,
+        "tags": sorted_tags(&kv.tags),
+    })
+}
+
+#[test]
+fn diff_should_classify_added_removed_and_modified_keys() {
+    let mut tagged = kv("same", "1");
+    tagged.tags.insert("owner".to_owned(), "team".to_owned());
+
+    let source = vec![
+        kv("added", "1"),
+        kv("modified", "new"),
+        tagged,
+        kv("equal", "1"),
+    ];
+    let target = vec![
+        kv("removed", "1"),
+        kv("modified", "old"),
+        kv("same", "1"),
+        kv("equal", "1"),
+    ];
+
+    let diff = diff_key_values(&source, &target).unwrap();
+    let keys = diff.changes.iter().map(|c| c.key()).collect::<Vec<&str>>();
+    assert_eq!(keys, vec!["added", "modified", "removed", "same"]);
+
+    assert!(matches!(diff.changes[0], Change::Added(_)));
+    assert!(matches!(diff.changes[1], Change::Modified { .. }));
+    assert!(matches!(diff.changes[2], Change::Removed(_)));
+    assert!(matches!(diff.changes[3], Change::Modified { .. }));
+}
+
+#[test]
+fn diff_should_render_unified_text_and_json() {
+    let diff = diff_key_values(
+        &[kv("added", "1"), kv("port", "8080")],
+        &[kv("port", "80"), kv("removed", "2")],
+    )
+    .unwrap();
+
+    assert_eq!(
+        diff.to_unified(),
+        "+ added = \"1\"\n~ port\n-   value = \"80\"\n+   value = \"8080\"\n- removed = \"2\"\n"
+    );
+
+    let json: Value = serde_json::from_str(&diff.to_json().unwrap()).unwrap();
+    assert_eq!(json[1]["change"], "modified");
+    assert_eq!(json[1]["fields"], json!(["value"]));
+    assert_eq!(json[1]["target"]["value"], "80");
+}
+
+#[test]
+fn diff_should_fail_on_keys_of_several_labels() {
+    let labeled = |label: &str| KeyValue {
+        label: Some(label.to_owned()),
+        ..kv("port", "80")
+    };
+
+    let error = diff_key_values(&[labeled("staging"), labeled("prod")], &[]).unwrap_err();
+    assert!(error.to_string().contains("port"));
+    assert!(diff_key_values(&[], &[labeled("staging"), labeled("prod")]).is_err());
+}
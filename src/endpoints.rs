@@ -1,38 +1,285 @@
+use crate::load_balancing::LoadBalancing;
+use crate::Exception;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use url::Url;
 
+#[derive(Clone, Copy)]
 pub(crate) enum EndpointUrl {
     KeyValues,
     Keys,
     Labels,
+    Snapshots,
+    Operations,
+    Locks,
+    Revisions,
 }
 pub(crate) const KEY_VALUE_ENDPOINT: &str = "kv";
 pub(crate) const KEYS_ENDPOINT: &str = "keys";
 pub(crate) const LABELS_ENDPOINT: &str = "labels";
+pub(crate) const SNAPSHOTS_ENDPOINT: &str = "snapshots";
+pub(crate) const OPERATIONS_ENDPOINT: &str = "operations";
+pub(crate) const LOCKS_ENDPOINT: &str = "locks";
+pub(crate) const REVISIONS_ENDPOINT: &str = "revisions";
+
+/// Everything but the RFC 3986 unreserved characters gets percent-encoded,
+/// both in path segments and in query values
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Time a failed endpoint is skipped, doubled on every consecutive failure
+const FAILOVER_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_FAILOVER_BACKOFF: Duration = Duration::from_secs(600);
+/// Weight of the latest sample in the average latency of an endpoint
+const LATENCY_SMOOTHING: f64 = 0.2;
 
 pub(crate) struct Endpoints {
     base_endpoint: String,
+    /// Replica endpoints, tried in order when the previous ones fail
+    replicas: Vec<String>,
+    health: Mutex<Vec<EndpointHealth>>,
+    last_served: Mutex<Option<usize>>,
+    load_balancing: LoadBalancing,
+    next_read: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct EndpointHealth {
+    failures: u32,
+    retry_after: Option<Instant>,
+    /// Moving average of the request latency, in seconds
+    latency: Option<f64>,
 }
 
 impl Endpoints {
     pub(crate) fn new<S: Into<String>>(config_endpoint: S) -> Endpoints {
         Endpoints {
             base_endpoint: config_endpoint.into(),
+            replicas: Vec::new(),
+            health: Mutex::new(vec![EndpointHealth::default()]),
+            last_served: Mutex::new(None),
+            load_balancing: LoadBalancing::default(),
+            next_read: AtomicUsize::new(0),
         }
     }
 
+    pub(crate) fn with_load_balancing(mut self, load_balancing: LoadBalancing) -> Endpoints {
+        self.load_balancing = load_balancing;
+        self
+    }
+
+    /// Adds failover replicas after the primary endpoint
+    pub(crate) fn with_replicas(mut self, replicas: Vec<String>) -> Endpoints {
+        self.health = Mutex::new(vec![EndpointHealth::default(); replicas.len() + 1]);
+        self.replicas = replicas;
+        self
+    }
+
     pub(crate) fn base_endpoint(&self) -> String {
         self.base_endpoint.clone()
     }
 
+    /// Primary endpoint followed by the replicas
+    pub(crate) fn all(&self) -> Vec<&str> {
+        std::iter::once(self.base_endpoint.as_str())
+            .chain(self.replicas.iter().map(|r| r.as_str()))
+            .collect()
+    }
+
+    /// Indexes of the endpoints to try, in order. Writes only go to the primary
+    /// endpoint. Reads are spread across the healthy endpoints following the load
+    /// balancing strategy, endpoints backing off after a failure come last, the
+    /// ones closest to recovery first
+    pub(crate) fn candidates(&self, read: bool) -> Vec<usize> {
+        if !read {
+            return vec![0];
+        }
+
+        let health = self.health.lock().unwrap();
+        let now = Instant::now();
+
+        let (mut available, mut backing_off): (Vec<usize>, Vec<usize>) =
+            (0..health.len()).partition(|&i| health[i].retry_after.is_none_or(|r| r <= now));
+        backing_off.sort_by_key(|&i| health[i].retry_after);
+
+        if available.len() > 1 {
+            let first = match self.load_balancing {
+                LoadBalancing::Failover => 0,
+                LoadBalancing::RoundRobin => self.next_read.fetch_add(1, Ordering::Relaxed),
+                LoadBalancing::Random => random() as usize,
+                LoadBalancing::LatencyWeighted => weighted_pick(&available, &health),
+            } % available.len();
+            available.rotate_left(first);
+        }
+
+        available.extend(backing_off);
+        available
+    }
+
+    pub(crate) fn mark_failed(&self, index: usize) {
+        let mut health = self.health.lock().unwrap();
+        let endpoint = &mut health[index];
+        endpoint.failures += 1;
+
+        let backoff = FAILOVER_BACKOFF
+            .checked_mul(1 << (endpoint.failures - 1).min(16))
+            .unwrap_or(MAX_FAILOVER_BACKOFF)
+            .min(MAX_FAILOVER_BACKOFF);
+        endpoint.retry_after = Some(Instant::now() + backoff);
+    }
+
+    pub(crate) fn mark_healthy(&self, index: usize, latency: Duration) {
+        let mut health = self.health.lock().unwrap();
+        let sample = latency.as_secs_f64();
+        let average = match health[index].latency {
+            Some(average) => average + LATENCY_SMOOTHING * (sample - average),
+            None => sample,
+        };
+        health[index] = EndpointHealth {
+            latency: Some(average),
+            ..Default::default()
+        };
+        *self.last_served.lock().unwrap() = Some(index);
+    }
+
+    /// Endpoint that answered the latest successful request
+    pub(crate) fn last_served(&self) -> Option<String> {
+        self.last_served
+            .lock()
+            .unwrap()
+            .map(|i| self.all()[i].to_owned())
+    }
+
+    /// Moves a url built for the primary endpoint to the endpoint at `index`
+    pub(crate) fn rebase(&self, url: &Url, index: usize) -> Result<Url, Exception> {
+        if index == 0 {
+            return Ok(url.clone());
+        }
+
+        let replica = self.replicas[index - 1].parse::<Url>()?;
+        let mut rebased = url.clone();
+        rebased
+            .set_scheme(replica.scheme())
+            .map_err(|_| format!("Invalid replica scheme {}", replica.scheme()))?;
+        rebased.set_host(replica.host_str())?;
+        rebased
+            .set_port(replica.port())
+            .map_err(|_| format!("Invalid replica endpoint {}", replica))?;
+
+        Ok(rebased)
+    }
+
     pub(crate) fn get_uri(&self, endpoint: EndpointUrl) -> Url {
         let e = match endpoint {
             EndpointUrl::KeyValues => format!("{}/{}", self.base_endpoint, KEY_VALUE_ENDPOINT),
             EndpointUrl::Keys => format!("{}/{}", self.base_endpoint, KEYS_ENDPOINT),
             EndpointUrl::Labels => format!("{}/{}", self.base_endpoint, LABELS_ENDPOINT),
+            EndpointUrl::Snapshots => format!("{}/{}", self.base_endpoint, SNAPSHOTS_ENDPOINT),
+            EndpointUrl::Operations => format!("{}/{}", self.base_endpoint, OPERATIONS_ENDPOINT),
+            EndpointUrl::Locks => format!("{}/{}", self.base_endpoint, LOCKS_ENDPOINT),
+            EndpointUrl::Revisions => format!("{}/{}", self.base_endpoint, REVISIONS_ENDPOINT),
         };
 
         e.parse::<Url>().unwrap()
     }
+
+    /// Returns a builder for urls under the given endpoint
+    pub(crate) fn url(&self, endpoint: EndpointUrl) -> UrlBuilder {
+        UrlBuilder {
+            url: self.get_uri(endpoint),
+            query: Vec::new(),
+        }
+    }
+}
+
+/// Position in `available` picked with a probability proportional to the inverse
+/// of the endpoint latency. Endpoints without latency samples are picked first
+fn weighted_pick(available: &[usize], health: &[EndpointHealth]) -> usize {
+    if let Some(position) = available.iter().position(|&i| health[i].latency.is_none()) {
+        return position;
+    }
+
+    let weights = available
+        .iter()
+        .map(|&i| 1.0 / health[i].latency.unwrap_or_default().max(1e-6))
+        .collect::<Vec<f64>>();
+    let mut target = (random() as f64 / u64::MAX as f64) * weights.iter().sum::<f64>();
+
+    for (position, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return position;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+/// Random number from the randomly keyed std hasher, good enough to spread requests
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
+
+/// Builds endpoint urls, percent-encoding path segments and query values
+pub(crate) struct UrlBuilder {
+    url: Url,
+    query: Vec<String>,
+}
+
+impl UrlBuilder {
+    /// Appends a path segment, such as a key name
+    pub(crate) fn segment(mut self, segment: &str) -> Result<Self, Exception> {
+        if segment == "." || segment == ".." {
+            return Err(format!("'{}' is not a valid path segment", segment).into());
+        }
+
+        let path = format!(
+            "{}/{}",
+            self.url.path().trim_end_matches('/'),
+            utf8_percent_encode(segment, COMPONENT)
+        );
+        self.url.set_path(&path);
+
+        Ok(self)
+    }
+
+    /// Appends a query parameter
+    pub(crate) fn query(mut self, name: &str, value: &str) -> Self {
+        self.query.push(format!(
+            "{}={}",
+            utf8_percent_encode(name, COMPONENT),
+            utf8_percent_encode(value, COMPONENT)
+        ));
+        self
+    }
+
+    /// Appends a query parameter when there is a value
+    pub(crate) fn optional_query(self, name: &str, value: Option<&str>) -> Self {
+        match value {
+            Some(value) => self.query(name, value),
+            None => self,
+        }
+    }
+
+    pub(crate) fn build(mut self) -> Url {
+        if !self.query.is_empty() {
+            self.url.set_query(Some(&self.query.join("&")));
+        }
+        self.url
+    }
 }
 
 #[test]
@@ -50,4 +297,146 @@ fn endpoints_url_test() {
         endpoints.get_uri(EndpointUrl::Labels).to_string(),
         "http://sample.io/labels"
     );
+    assert_eq!(
+        endpoints.get_uri(EndpointUrl::Snapshots).to_string(),
+        "http://sample.io/snapshots"
+    );
+    assert_eq!(
+        endpoints.get_uri(EndpointUrl::Operations).to_string(),
+        "http://sample.io/operations"
+    );
+    assert_eq!(
+        endpoints.get_uri(EndpointUrl::Locks).to_string(),
+        "http://sample.io/locks"
+    );
+    assert_eq!(
+        endpoints.get_uri(EndpointUrl::Revisions).to_string(),
+        "http://sample.io/revisions"
+    );
+}
+
+#[test]
+fn failed_endpoints_should_be_tried_last() {
+    let endpoints = Endpoints::new("https://primary.io").with_replicas(vec![
+        "https://east.io".to_owned(),
+        "http://west.io:8080".to_owned(),
+    ]);
+    assert_eq!(endpoints.candidates(true), vec![0, 1, 2]);
+
+    endpoints.mark_failed(0);
+    assert_eq!(endpoints.candidates(true), vec![1, 2, 0]);
+
+    endpoints.mark_failed(1);
+    endpoints.mark_failed(1);
+    assert_eq!(endpoints.candidates(true), vec![2, 0, 1]);
+
+    endpoints.mark_healthy(0, Duration::from_millis(10));
+    assert_eq!(endpoints.candidates(true), vec![0, 2, 1]);
+    assert_eq!(endpoints.candidates(false), vec![0]);
+}
+
+#[test]
+fn reads_should_be_balanced_across_healthy_endpoints() {
+    let endpoints = Endpoints::new("https://primary.io")
+        .with_replicas(vec![
+            "https://east.io".to_owned(),
+            "https://west.io".to_owned(),
+        ])
+        .with_load_balancing(LoadBalancing::RoundRobin);
+
+    let firsts = (0..4)
+        .map(|_| endpoints.candidates(true)[0])
+        .collect::<Vec<usize>>();
+    assert_eq!(firsts, vec![0, 1, 2, 0]);
+    assert_eq!(endpoints.candidates(true), vec![1, 2, 0]);
+
+    endpoints.mark_failed(2);
+    assert_eq!(endpoints.candidates(true), vec![1, 0, 2]);
+    assert_eq!(endpoints.candidates(false), vec![0]);
+}
+
+#[test]
+fn latency_weighted_reads_should_prefer_fast_endpoints() {
+    let endpoints = Endpoints::new("https://primary.io")
+        .with_replicas(vec!["https://east.io".to_owned()])
+        .with_load_balancing(LoadBalancing::LatencyWeighted);
+
+    endpoints.mark_healthy(0, Duration::from_millis(1000));
+    assert_eq!(endpoints.candidates(true)[0], 1);
+
+    endpoints.mark_healthy(1, Duration::from_millis(1));
+    let fast = (0..100)
+        .filter(|_| endpoints.candidates(true)[0] == 1)
+        .count();
+    assert!(fast > 90);
+}
+
+#[test]
+fn urls_should_be_rebased_on_replicas() {
+    let endpoints =
+        Endpoints::new("https://primary.io").with_replicas(vec!["http://west.io:8080".to_owned()]);
+    let url = endpoints
+        .url(EndpointUrl::KeyValues)
+        .segment("app:port")
+        .unwrap()
+        .query("label", "prod")
+        .build();
+
+    assert_eq!(endpoints.rebase(&url, 0).unwrap(), url);
+    assert_eq!(
+        endpoints.rebase(&url, 1).unwrap().as_str(),
+        "http://west.io:8080/kv/app%3Aport?label=prod"
+    );
+}
+
+#[test]
+fn url_builder_should_encode_segments_and_query_values() {
+    let endpoints = Endpoints::new("http://sample.io");
+    let url = endpoints
+        .url(EndpointUrl::KeyValues)
+        .segment("app/Settings?#%1 ñ")
+        .unwrap()
+        .query("label", "a&b=c+d")
+        .build();
+
+    assert_eq!(
+        url.as_str(),
+        "http://sample.io/kv/app%2FSettings%3F%23%251%20%C3%B1?label=a%26b%3Dc%2Bd"
+    );
+}
+
+#[test]
+fn url_builder_should_reject_dot_segments() {
+    let endpoints = Endpoints::new("http://sample.io");
+    assert!(endpoints.url(EndpointUrl::KeyValues).segment(".").is_err());
+    assert!(endpoints.url(EndpointUrl::KeyValues).segment("..").is_err());
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn url_builder_should_round_trip_any_key_and_label(
+        key in proptest::prelude::any::<String>(),
+        label in proptest::prelude::any::<String>()
+    ) {
+        proptest::prop_assume!(key != "." && key != "..");
+
+        let endpoints = Endpoints::new("http://sample.io");
+        let url = endpoints
+            .url(EndpointUrl::KeyValues)
+            .segment(&key)
+            .unwrap()
+            .query("label", &label)
+            .build();
+
+        let segment = url.path_segments().unwrap().next_back().unwrap();
+        let decoded = percent_encoding::percent_decode_str(segment).decode_utf8().unwrap();
+        proptest::prop_assert_eq!(decoded, key);
+
+        let (name, value) = url.query_pairs().next().unwrap();
+        proptest::prop_assert_eq!(name, "label");
+        proptest::prop_assert_eq!(value, label);
+
+        proptest::prop_assert_eq!(url.as_str().parse::<Url>().unwrap(), url);
+    }
 }
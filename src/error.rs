@@ -1,11 +1,25 @@
-use serde::export::fmt::Display;
+use crate::pipeline::Headers;
+use crate::telemetry::REQUEST_ID_HEADER;
 use std::error::Error;
+use std::fmt::Display;
 use std::fmt::Formatter;
+use std::time::Duration;
+
+/// Status returned by the service when a key value no longer exists, for deletions
+pub(crate) const NO_CONTENT_STATUS: usize = 204;
+pub(crate) const NOT_FOUND_STATUS: usize = 404;
+/// Status returned by the service when a locked key value is modified
+pub(crate) const LOCKED_STATUS: usize = 409;
+/// Status returned by the service when the etag precondition is not met
+pub(crate) const PRECONDITION_FAILED_STATUS: usize = 412;
+/// Status returned by the service when requests are throttled
+pub(crate) const THROTTLED_STATUS: usize = 429;
 
 #[derive(Debug, Clone)]
 pub struct HttpError {
     status: usize,
     url: String,
+    headers: Headers,
 }
 
 impl HttpError {
@@ -13,8 +27,34 @@ impl HttpError {
         HttpError {
             status: err,
             url: url.into(),
+            headers: Headers::default(),
         }
     }
+
+    /// Keeps the headers of the failed response
+    pub fn with_headers(mut self, headers: Headers) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Http status code returned by the service
+    pub fn status(&self) -> usize {
+        self.status
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Headers of the failed response, empty when the error was not built from one
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Id of the failed request in the service, to be quoted in support tickets
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers.get(REQUEST_ID_HEADER)
+    }
 }
 
 impl Error for HttpError {}
@@ -25,6 +65,66 @@ impl Display for HttpError {
             f,
             "Http request error, code: {}, url: {}",
             self.status, self.url
-        )
+        )?;
+        match self.request_id() {
+            Some(id) => write!(f, ", request id: {}", id),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Phase of a request that exceeded its time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// Establishing the connection, TLS handshake included
+    Connect,
+    /// Receiving the response headers once the request is sent
+    Headers,
+    /// Reading the response body
+    BodyRead,
+    /// The whole call, including failover attempts
+    Total,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimeoutError {
+    kind: TimeoutKind,
+    after: Duration,
+    url: Option<String>,
+}
+
+impl TimeoutError {
+    pub fn new(kind: TimeoutKind, after: Duration, url: Option<&str>) -> Self {
+        TimeoutError {
+            kind,
+            after,
+            url: url.map(|u| u.to_owned()),
+        }
+    }
+
+    pub fn kind(&self) -> TimeoutKind {
+        self.kind
+    }
+
+    /// Time limit that was exceeded
+    pub fn after(&self) -> Duration {
+        self.after
+    }
+
+    /// Url of the timed out request, `None` for whole calls
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+}
+
+impl Error for TimeoutError {}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{:?} timeout after {:?}", self.kind, self.after)?;
+        if let Some(url) = &self.url {
+            write!(f, ", url: {}", url)?;
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,614 @@
+//! Conversions between key values and common configuration file formats
+//!
+//! # Examples
+//! ```
+//! use azure_app_configuration::formats::{export, import, Format, FormatOptions};
+//!
+//! let options = FormatOptions {
+//!     separator: Some(":".to_owned()),
+//!     label: Some("prod".to_owned()),
+//!     ..Default::default()
+//! };
+//!
+//! let key_values = import(r#"{ "app": { "port": "8080" } }"#, Format::Json, &options).unwrap();
+//! assert_eq!(key_values[0].key, "app:port");
+//! assert_eq!(key_values[0].label, Some("prod".to_owned()));
+//!
+//! let dotenv = export(&key_values, Format::Dotenv, &options).unwrap();
+//! assert_eq!(dotenv, "app:port=8080\n");
+//! ```
+use crate::media_type;
+#[cfg(test)]
+use crate::model::kv;
+use crate::model::KeyValue;
+use crate::Exception;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    /// Java `.properties`
+    Properties,
+    /// `.env` files
+    Dotenv,
+    /// Json list of key values keeping every field (label, content type, tags, etag...)
+    KvSet,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptions {
+    /// Separator used to nest keys in json, yaml and toml, keys are kept flat when `None`
+    pub separator: Option<String>,
+    /// Label set on imported key values
+    pub label: Option<String>,
+    /// Content type set on imported key values. When it is a json content type
+    /// values are read and written as json instead of plain strings
+    pub content_type: Option<String>,
+    /// Tags set on imported key values
+    pub tags: HashMap<String, String>,
+}
+
+/// Serializes the key values in the given format.
+/// Labels, tags and content types are only kept by `Format::KvSet`
+pub fn export(
+    key_values: &[KeyValue],
+    format: Format,
+    options: &FormatOptions,
+) -> Result<String, Exception> {
+    match format {
+        Format::Json => Ok(serde_json::to_string_pretty(&to_tree(
+            key_values, options,
+        )?)?),
+        Format::Yaml => Ok(serde_yaml::to_string(&to_tree(key_values, options)?)?),
+        Format::Toml => Ok(toml::to_string(&toml::Value::try_from(to_tree(
+            key_values, options,
+        )?)?)?),
+        Format::Properties => Ok(export_properties(&unique(key_values)?)),
+        Format::Dotenv => Ok(export_dotenv(&unique(key_values)?)),
+        Format::KvSet => Ok(serde_json::to_string_pretty(&KvSet {
+            items: key_values.to_vec(),
+        })?),
+    }
+}
+
+/// Parses key values from the given format, applying the label, content type
+/// and tags of the options. `Format::KvSet` keeps the ones stored in the file
+pub fn import(
+    input: &str,
+    format: Format,
+    options: &FormatOptions,
+) -> Result<Vec<KeyValue>, Exception> {
+    match format {
+        Format::Json => from_tree(serde_json::from_str(input)?, options),
+        Format::Yaml => from_tree(serde_yaml::from_str(input)?, options),
+        Format::Toml => from_tree(toml_to_json(toml::from_str(input)?), options),
+        Format::Properties => Ok(parse_properties(input)
+            .into_iter()
+            .map(|(k, v)| new_key_value(k, v, options))
+            .collect()),
+        Format::Dotenv => Ok(parse_dotenv(input)?
+            .into_iter()
+            .map(|(k, v)| new_key_value(k, v, options))
+            .collect()),
+        Format::KvSet => Ok(serde_json::from_str::<KvSet>(input)?.items),
+    }
+}
+
+/// Whether the content type is `application/json` or an `application/*+json` media type
+pub(crate) fn is_json_content_type(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|c| media_type::is_json(&media_type::media_type(c)))
+}
+
+fn new_key_value(key: String, value: String, options: &FormatOptions) -> KeyValue {
+    KeyValue {
+        key,
+        value,
+        label: options.label.clone(),
+        content_type: options.content_type.clone(),
+        tags: options.tags.clone(),
+        ..Default::default()
+    }
+}
+
+fn unique(key_values: &[KeyValue]) -> Result<Vec<(&str, &str)>, Exception> {
+    let mut seen = std::collections::HashSet::new();
+    let mut pairs = Vec::new();
+
+    for kv in key_values {
+        if !seen.insert(kv.key.as_str()) {
+            return Err(format!("Key {} is duplicated, export a single label", kv.key).into());
+        }
+        pairs.push((kv.key.as_str(), kv.value.as_str()));
+    }
+
+    Ok(pairs)
+}
+
+fn to_tree(key_values: &[KeyValue], options: &FormatOptions) -> Result<Value, Exception> {
+    let mut root = Map::new();
+
+    for kv in key_values {
+        let value = if is_json_content_type(kv.content_type.as_deref()) {
+            serde_json::from_str(&kv.value).unwrap_or_else(|_| Value::String(kv.value.clone()))
+        } else {
+            Value::String(kv.value.clone())
+        };
+
+        let parts: Vec<&str> = match &options.separator {
+            Some(sep) if !sep.is_empty() => kv.key.split(sep.as_str()).collect(),
+            _ => vec![kv.key.as_str()],
+        };
+
+        let (last, sections) = parts.split_last().unwrap();
+        let mut node = &mut root;
+        for section in sections {
+            node = match node
+                .entry(section.to_string())
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(m) => m,
+                _ => return Err(format!("Key {} conflicts with a shorter key", kv.key).into()),
+            };
+        }
+
+        if node.insert(last.to_string(), value).is_some() {
+            return Err(format!(
+                "Key {} is duplicated or conflicts with a longer key",
+                kv.key
+            )
+            .into());
+        }
+    }
+
+    Ok(Value::Object(root))
+}
+
+fn from_tree(value: Value, options: &FormatOptions) -> Result<Vec<KeyValue>, Exception> {
+    let root = match value {
+        Value::Object(m) => m,
+        _ => return Err("Expected an object at the top level".into()),
+    };
+
+    let mut key_values = Vec::new();
+    for (key, value) in root {
+        flatten(key, value, options, &mut key_values);
+    }
+
+    Ok(key_values)
+}
+
+fn flatten(key: String, value: Value, options: &FormatOptions, key_values: &mut Vec<KeyValue>) {
+    let json = is_json_content_type(options.content_type.as_deref());
+    let sep = options.separator.as_deref().filter(|s| !s.is_empty());
+
+    match (value, sep) {
+        (Value::Object(m), Some(sep)) => {
+            for (k, v) in m {
+                flatten(format!("{}{}{}", key, sep, k), v, options, key_values);
+            }
+        }
+        (Value::Array(a), Some(sep)) if !json => {
+            for (i, v) in a.into_iter().enumerate() {
+                flatten(format!("{}{}{}", key, sep, i), v, options, key_values);
+            }
+        }
+        (Value::String(s), _) if !json => key_values.push(new_key_value(key, s, options)),
+        (v, _) => key_values.push(new_key_value(key, v.to_string(), options)),
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+fn escape_properties(value: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' if is_key => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if is_key || i == 0 => escaped.push_str("\\ "),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn unescape_properties(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('t') => unescaped.push('\t'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    Some(u) => unescaped.push(u),
+                    None => unescaped.push_str(&code),
+                }
+            }
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+
+    unescaped
+}
+
+fn export_properties(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| {
+            format!(
+                "{}={}\n",
+                escape_properties(k, true),
+                escape_properties(v, false)
+            )
+        })
+        .collect()
+}
+
+fn parse_properties(input: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut logical = String::new();
+
+    for line in input.lines() {
+        let line = if logical.is_empty() {
+            line.trim_start()
+        } else {
+            line.trim_start_matches([' ', '\t', '\u{c}'])
+        };
+
+        if logical.is_empty() && (line.is_empty() || line.starts_with('#') || line.starts_with('!'))
+        {
+            continue;
+        }
+
+        let trailing = line.len() - line.trim_end_matches('\\').len();
+        if trailing % 2 == 1 {
+            logical.push_str(&line[..line.len() - 1]);
+            continue;
+        }
+
+        logical.push_str(line);
+        pairs.push(split_property(&logical));
+        logical.clear();
+    }
+
+    if !logical.is_empty() {
+        pairs.push(split_property(&logical));
+    }
+
+    pairs
+}
+
+fn split_property(line: &str) -> (String, String) {
+    let mut key_end = line.len();
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            key_end = i;
+            break;
+        }
+    }
+
+    let rest = line[key_end..].trim_start();
+    let rest = match rest.chars().next() {
+        Some('=') | Some(':') => rest[1..].trim_start(),
+        _ => rest,
+    };
+
+    (
+        unescape_properties(&line[..key_end]),
+        unescape_properties(rest),
+    )
+}
+
+fn export_dotenv(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(k, v)| {
+            let plain = v
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_.,:/@+%".contains(c));
+
+            if plain {
+                format!("{}={}\n", k, v)
+            } else {
+                let quoted = v
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r")
+                    .replace('\t', "\\t");
+                format!("{}=\"{}\"\n", k, quoted)
+            }
+        })
+        .collect()
+}
+
+fn parse_dotenv(input: &str) -> Result<Vec<(String, String)>, Exception> {
+    let mut pairs = Vec::new();
+    let mut lines = input.lines();
+
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let separator = line
+            .find('=')
+            .ok_or_else(|| format!("Invalid dotenv line: {}", line))?;
+        let key = line[..separator].trim().to_string();
+        let raw = line[separator + 1..].trim_start();
+
+        let value = if let Some(quoted) = raw.strip_prefix('"') {
+            let mut quoted = quoted.to_string();
+            while !has_closing_quote(&quoted) {
+                match lines.next() {
+                    Some(next) => {
+                        quoted.push('\n');
+                        quoted.push_str(next);
+                    }
+                    None => return Err(format!("Unclosed quote for key {}", key).into()),
+                }
+            }
+            unescape_dotenv(&quoted)
+        } else if let Some(quoted) = raw.strip_prefix('\'') {
+            match quoted.find('\'') {
+                Some(end) => quoted[..end].to_string(),
+                None => return Err(format!("Unclosed quote for key {}", key).into()),
+            }
+        } else {
+            match raw.find(" #") {
+                Some(comment) => raw[..comment].trim_end().to_string(),
+                None => raw.trim_end().to_string(),
+            }
+        };
+
+        pairs.push((key, value));
+    }
+
+    Ok(pairs)
+}
+
+fn has_closing_quote(value: &str) -> bool {
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Unescapes a double quoted value up to its closing quote
+fn unescape_dotenv(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                Some(other) => unescaped.push(other),
+                None => {}
+            },
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct KvSet {
+    items: Vec<KeyValue>,
+}
+
+#[test]
+fn nested_json_should_round_trip_with_separator() {
+    let options = FormatOptions {
+        separator: Some(":".to_owned()),
+        ..Default::default()
+    };
+    let key_values = vec![kv("app:db:host", "localhost"), kv("app:port", "8080")];
+
+    let json = export(&key_values, Format::Json, &options).unwrap();
+    let value: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["app"]["db"]["host"], "localhost");
+
+    let imported = import(&json, Format::Json, &options).unwrap();
+    assert_eq!(imported[0].key, "app:db:host");
+    assert_eq!(imported[1].key, "app:port");
+    assert_eq!(imported[1].value, "8080");
+}
+
+#[test]
+fn nested_export_should_fail_on_conflicting_keys() {
+    let options = FormatOptions {
+        separator: Some(":".to_owned()),
+        ..Default::default()
+    };
+    let key_values = vec![kv("app", "1"), kv("app:port", "8080")];
+
+    assert!(export(&key_values, Format::Yaml, &options).is_err());
+}
+
+#[test]
+fn yaml_and_toml_should_flatten_scalars_and_arrays() {
+    let options = FormatOptions {
+        separator: Some(".".to_owned()),
+        label: Some("prod".to_owned()),
+        ..Default::default()
+    };
+
+    let yaml = import(
+        "app:\n  retries: 3\n  hosts: [a, b]\n",
+        Format::Yaml,
+        &options,
+    )
+    .unwrap();
+    let toml = import(
+        "[app]\nretries = 3\nhosts = [\"a\", \"b\"]\n",
+        Format::Toml,
+        &options,
+    )
+    .unwrap();
+
+    for key_values in &[yaml, toml] {
+        let pairs: Vec<(&str, &str)> = key_values
+            .iter()
+            .map(|k| (k.key.as_str(), k.value.as_str()))
+            .collect();
+        assert!(pairs.contains(&("app.retries", "3")));
+        assert!(pairs.contains(&("app.hosts.0", "a")));
+        assert!(pairs.contains(&("app.hosts.1", "b")));
+        assert!(key_values
+            .iter()
+            .all(|k| k.label == Some("prod".to_owned())));
+    }
+}
+
+#[test]
+fn properties_should_round_trip_escaped_keys_and_values() {
+    let key_values = vec![
+        kv("app:key with=chars", " multi\nline\\value"),
+        kv("ünicode", "välue"),
+    ];
+
+    let properties = export(&key_values, Format::Properties, &FormatOptions::default()).unwrap();
+    let imported = import(&properties, Format::Properties, &FormatOptions::default()).unwrap();
+
+    assert_eq!(imported[0].key, key_values[0].key);
+    assert_eq!(imported[0].value, key_values[0].value);
+    assert_eq!(imported[1].key, key_values[1].key);
+    assert_eq!(imported[1].value, key_values[1].value);
+}
+
+#[test]
+fn properties_should_parse_continuations_and_separators() {
+    let input = "# comment\nkey1 = value1\nkey2:value2\nkey3 value\\\n    continued\n";
+    let pairs = parse_properties(input);
+
+    assert_eq!(
+        pairs,
+        vec![
+            ("key1".to_owned(), "value1".to_owned()),
+            ("key2".to_owned(), "value2".to_owned()),
+            ("key3".to_owned(), "valuecontinued".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn dotenv_should_round_trip_quoted_values() {
+    let key_values = vec![
+        kv("PLAIN", "value"),
+        kv("QUOTED", "with \"quotes\" and\nnew line"),
+    ];
+
+    let dotenv = export(&key_values, Format::Dotenv, &FormatOptions::default()).unwrap();
+    let imported = import(&dotenv, Format::Dotenv, &FormatOptions::default()).unwrap();
+
+    assert_eq!(imported[0].value, "value");
+    assert_eq!(imported[1].value, key_values[1].value);
+
+    let pairs =
+        parse_dotenv("export A='lit\\n' # c\nB=plain # comment\nC=\"multi\nline\"\n").unwrap();
+    assert_eq!(pairs[0].1, "lit\\n");
+    assert_eq!(pairs[1].1, "plain");
+    assert_eq!(pairs[2].1, "multi\nline");
+}
+
+#[test]
+fn kvset_should_round_trip_every_field() {
+    let mut tags = HashMap::new();
+    tags.insert("owner".to_owned(), "team".to_owned());
+    let key_values = vec![KeyValue {
+        etag: "etag".to_owned(),
+        key: "app:port".to_owned(),
+        label: Some("prod".to_owned()),
+        value: "8080".to_owned(),
+        content_type: Some("text/plain".to_owned()),
+        last_modified: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_568_102_400)),
+        locked: true,
+        tags,
+    }];
+
+    let kvset = export(&key_values, Format::KvSet, &FormatOptions::default()).unwrap();
+    let imported = import(&kvset, Format::KvSet, &FormatOptions::default()).unwrap();
+
+    assert_eq!(imported, key_values);
+}
+
+#[test]
+fn json_content_type_should_keep_json_values() {
+    let options = FormatOptions {
+        content_type: Some("application/json".to_owned()),
+        ..Default::default()
+    };
+
+    let imported = import(
+        r#"{ "list": [1, 2], "name": "app" }"#,
+        Format::Json,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(imported[0].value, "[1,2]");
+    assert_eq!(imported[1].value, "\"app\"");
+
+    let json = export(&imported, Format::Json, &options).unwrap();
+    let value: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["list"], serde_json::json!([1, 2]));
+    assert!(is_json_content_type(Some(
+        "application/vnd.microsoft.appconfig.ff+json;charset=utf-8"
+    )));
+}
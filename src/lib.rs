@@ -3,9 +3,28 @@ extern crate serde_derive;
 
 type Exception = Box<dyn std::error::Error + Send + Sync + 'static>;
 
+pub mod backup;
+pub mod batch;
+pub mod cache;
 pub mod client;
+pub mod diff;
 mod endpoints;
 pub mod error;
+pub mod formats;
+pub mod load_balancing;
+mod media_type;
+pub mod metrics;
 pub mod model;
+pub mod pipeline;
+pub mod redact;
 mod request_sign;
+pub mod response;
 pub mod search_label;
+pub mod select_field;
+pub mod set_key;
+pub mod sync;
+pub mod sync_token;
+mod telemetry;
+pub mod timeout;
+pub mod transport;
+pub mod typed_value;
@@ -0,0 +1,15 @@
+/// How read requests are spread across the healthy endpoints.
+/// Writes always go to the primary endpoint, replicas are read only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadBalancing {
+    /// Reads go to the first healthy endpoint, replicas are only used on failover
+    #[default]
+    Failover,
+    /// Reads rotate across the healthy endpoints
+    RoundRobin,
+    /// Reads go to a random healthy endpoint
+    Random,
+    /// Reads go to a random healthy endpoint, weighted by the inverse of its
+    /// average latency so faster endpoints receive more requests
+    LatencyWeighted,
+}
@@ -0,0 +1,48 @@
+//! Parsing of key value content types, shared by the formats, redaction and
+//! typed values so they agree on what a content type announces
+use crate::typed_value::JSON_CONTENT_TYPE;
+
+/// Media type of a content type, in lower case and without its parameters
+pub(crate) fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_lowercase()
+}
+
+/// Value of a content type parameter, unquoted, parameter names are case insensitive
+pub(crate) fn parameter(content_type: &str, name: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|p| {
+        let (key, value) = p.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether the media type is `application/json` or an `application/*+json` one
+pub(crate) fn is_json(media_type: &str) -> bool {
+    media_type == JSON_CONTENT_TYPE
+        || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+#[test]
+fn content_types_should_be_parsed() {
+    let content_type = "Application/Problem+JSON; Charset=utf-8; profile=\"https://x/ai\"";
+
+    assert_eq!(media_type(content_type), "application/problem+json");
+    assert!(is_json(&media_type(content_type)));
+    assert_eq!(parameter(content_type, "charset").as_deref(), Some("utf-8"));
+    assert_eq!(
+        parameter(content_type, "profile").as_deref(),
+        Some("https://x/ai")
+    );
+    assert_eq!(parameter(content_type, "version"), None);
+    assert_eq!(media_type(" text/plain "), "text/plain");
+    assert!(!is_json("text/plain"));
+    assert!(!is_json("text/x+json"));
+}
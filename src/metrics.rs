@@ -0,0 +1,133 @@
+//! Pluggable metrics of the client requests
+//!
+//! Every http attempt is reported to the `MetricsRecorder` of the client. The
+//! `metrics` feature adds `MetricsCrateRecorder`, which forwards them to the
+//! [metrics](https://docs.rs/metrics) facade.
+use crate::error::THROTTLED_STATUS;
+use std::time::Duration;
+
+/// Outcome of a single http attempt
+#[derive(Debug, Clone)]
+pub struct RequestMetrics<'a> {
+    /// Method and endpoint of the request, such as `GET kv` or `PUT locks`
+    pub operation: &'a str,
+    /// Response status, `None` for transport errors and timeouts
+    pub status: Option<u16>,
+    pub latency: Duration,
+    /// Endpoint attempt, 0 for the first endpoint and greater after failovers to replicas
+    pub attempt: usize,
+    /// Retry at the endpoint, 0 for the first try and greater when a policy asked to retry
+    pub retry: u32,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+}
+
+impl<'a> RequestMetrics<'a> {
+    pub fn is_retry(&self) -> bool {
+        self.retry > 0
+    }
+
+    /// First try at a replica after the previous endpoint failed
+    pub fn is_failover(&self) -> bool {
+        self.attempt > 0 && self.retry == 0
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.status.map(usize::from) == Some(THROTTLED_STATUS)
+    }
+}
+
+/// Receives the client metrics, every method does nothing by default
+pub trait MetricsRecorder: Send + Sync {
+    /// Called after every http attempt
+    fn request(&self, _request: &RequestMetrics) {}
+}
+
+/// Recorder used when none is configured
+pub(crate) struct NoopRecorder;
+
+impl MetricsRecorder for NoopRecorder {}
+
+/// Forwards the client metrics to the `metrics` crate facade:
+/// - `appconfig_requests_total` counter by operation and status
+/// - `appconfig_request_duration_seconds` histogram by operation
+/// - `appconfig_retries_total`, `appconfig_failovers_total` and `appconfig_throttled_total`
+///   counters by operation
+/// - `appconfig_bytes_sent_total` and `appconfig_bytes_received_total` counters
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsCrateRecorder;
+
+#[cfg(feature = "metrics")]
+impl MetricsRecorder for MetricsCrateRecorder {
+    fn request(&self, request: &RequestMetrics) {
+        let operation = request.operation.to_string();
+        let status = request
+            .status
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "error".to_owned());
+
+        ::metrics::counter!(
+            "appconfig_requests_total",
+            "operation" => operation.clone(),
+            "status" => status
+        )
+        .increment(1);
+        ::metrics::histogram!(
+            "appconfig_request_duration_seconds",
+            "operation" => operation.clone()
+        )
+        .record(request.latency.as_secs_f64());
+
+        if request.is_retry() {
+            ::metrics::counter!("appconfig_retries_total", "operation" => operation.clone())
+                .increment(1);
+        }
+        if request.is_failover() {
+            ::metrics::counter!("appconfig_failovers_total", "operation" => operation.clone())
+                .increment(1);
+        }
+        if request.is_throttled() {
+            ::metrics::counter!("appconfig_throttled_total", "operation" => operation).increment(1);
+        }
+
+        ::metrics::counter!("appconfig_bytes_sent_total").increment(request.bytes_sent as u64);
+        ::metrics::counter!("appconfig_bytes_received_total")
+            .increment(request.bytes_received as u64);
+    }
+}
+
+/// Operation name of a request, its method and first path segment
+pub(crate) fn operation_name(method: &http::Method, url: &url::Url) -> String {
+    let endpoint = url
+        .path_segments()
+        .and_then(|mut segments| segments.next())
+        .unwrap_or("");
+    format!("{} {}", method, endpoint)
+}
+
+#[test]
+fn operation_should_be_method_and_endpoint() {
+    let url = "https://sample.io/kv/app%2Fkey?label=prod"
+        .parse::<url::Url>()
+        .unwrap();
+    assert_eq!(operation_name(&http::Method::PUT, &url), "PUT kv");
+}
+
+#[test]
+fn retries_and_failovers_should_be_told_apart() {
+    let request = |attempt, retry| RequestMetrics {
+        operation: "GET kv",
+        status: Some(200),
+        latency: Duration::from_millis(5),
+        attempt,
+        retry,
+        bytes_sent: 0,
+        bytes_received: 0,
+    };
+
+    assert!(!request(0, 0).is_retry() && !request(0, 0).is_failover());
+    assert!(request(0, 2).is_retry() && !request(0, 2).is_failover());
+    assert!(!request(1, 0).is_retry() && request(1, 0).is_failover());
+    assert!(request(1, 1).is_retry() && !request(1, 1).is_failover());
+}
@@ -1,43 +1,519 @@
+use crate::redact::{redact_value, REDACTED};
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+#[cfg(test)]
+use std::time::{Duration, UNIX_EPOCH};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Key {
     name: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Key {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Key { name: name.into() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Keys {
     pub items: Vec<Key>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Label {
     name: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl Label {
+    pub fn new<S: Into<String>>(name: Option<S>) -> Self {
+        Label {
+            name: name.map(|n| n.into()),
+        }
+    }
+
+    /// Label name, `None` for key values without label
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Labels {
     pub items: Vec<Label>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct KeyValues {
     pub items: Vec<KeyValue>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// Page of a listing, `next_link` points to the next page when there are more items
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Page<T> {
+    pub items: Vec<T>,
+    #[serde(rename = "@nextLink", default)]
+    pub next_link: Option<String>,
+}
+
+/// Key value with every field serialized, so it can be persisted as it is.
+/// Requests writing it only send the value, content type and tags
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct KeyValue {
-    #[serde(skip_serializing)]
-    pub etag: String,
-    #[serde(skip_serializing)]
     pub key: String,
-    #[serde(skip_serializing)]
-    pub label: Option<String>,
     pub value: String,
+    pub label: Option<String>,
     pub content_type: Option<String>,
-    #[serde(skip_serializing)]
-    pub last_modified: String,
-    #[serde(skip_serializing)]
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub etag: String,
+    /// Serialized as an RFC 3339 timestamp, `None` when the service did not send it
+    #[serde(default, with = "rfc3339")]
+    pub last_modified: Option<SystemTime>,
+    #[serde(default)]
     pub locked: bool,
+}
+
+/// Tags are hashed in key order, as they are compared regardless of order
+impl Hash for KeyValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+        self.value.hash(state);
+        self.label.hash(state);
+        self.content_type.hash(state);
+        let mut tags = self.tags.iter().collect::<Vec<(&String, &String)>>();
+        tags.sort();
+        tags.hash(state);
+        self.etag.hash(state);
+        self.last_modified.hash(state);
+        self.locked.hash(state);
+    }
+}
+
+/// Body of the requests writing a key value
+#[derive(Serialize, Debug)]
+pub(crate) struct SetKeyValueBody<'a> {
+    pub value: &'a str,
+    pub content_type: Option<&'a str>,
+    pub tags: &'a HashMap<String, String>,
+}
+
+impl<'a> From<&'a KeyValue> for SetKeyValueBody<'a> {
+    fn from(kv: &'a KeyValue) -> Self {
+        SetKeyValueBody {
+            value: &kv.value,
+            content_type: kv.content_type.as_deref(),
+            tags: &kv.tags,
+        }
+    }
+}
+
+/// Optional timestamps in the RFC 3339 format of the service, such as
+/// `2017-12-05T02:41:26+00:00`. Empty strings, which stood for missing timestamps
+/// in earlier archives and exports, are read as `None`
+mod rfc3339 {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use time::format_description::well_known::Rfc3339;
+    use time::OffsetDateTime;
+
+    pub(super) fn serialize<S: Serializer>(
+        timestamp: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp,
+            None => return serializer.serialize_none(),
+        };
+
+        let nanos = match timestamp.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_nanos() as i128,
+            Err(before) => -(before.duration().as_nanos() as i128),
+        };
+        let formatted = OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .ok()
+            .and_then(|t| t.format(&Rfc3339).ok())
+            .ok_or_else(|| S::Error::custom("Timestamp out of the RFC 3339 range"))?;
+        serializer.serialize_str(&formatted)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            None => Ok(None),
+            Some(timestamp) if timestamp.trim().is_empty() => Ok(None),
+            Some(timestamp) => OffsetDateTime::parse(timestamp.trim(), &Rfc3339)
+                .map(|t| Some(SystemTime::from(t)))
+                .map_err(|_| D::Error::custom(format!("Invalid RFC 3339 timestamp {}", timestamp))),
+        }
+    }
+}
+
+/// Sensitive values are redacted, see the `redact` module
+impl fmt::Debug for KeyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyValue")
+            .field("etag", &self.etag)
+            .field("key", &self.key)
+            .field("label", &self.label)
+            .field(
+                "value",
+                &redact_value(&self.key, self.content_type.as_deref(), &self.value),
+            )
+            .field("content_type", &self.content_type)
+            .field("last_modified", &self.last_modified)
+            .field("locked", &self.locked)
+            .field("tags", &self.tags)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartialKeyValues {
+    pub items: Vec<PartialKeyValue>,
+}
+
+/// Key value retrieved with a `$select` projection, fields not selected are `None`
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct PartialKeyValue {
+    pub etag: Option<String>,
+    pub key: Option<String>,
+    pub label: Option<String>,
+    pub value: Option<String>,
+    pub content_type: Option<String>,
+    #[serde(default, with = "rfc3339")]
+    pub last_modified: Option<SystemTime>,
+    pub locked: Option<bool>,
+    pub tags: Option<HashMap<String, String>>,
+}
+
+/// Masks sensitive values like `KeyValue`, and every value selected without its key
+/// as its sensitivity cannot be told
+impl fmt::Debug for PartialKeyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.value.as_deref().map(|value| match &self.key {
+            Some(key) => redact_value(key, self.content_type.as_deref(), value),
+            None => REDACTED,
+        });
+
+        f.debug_struct("PartialKeyValue")
+            .field("etag", &self.etag)
+            .field("key", &self.key)
+            .field("label", &self.label)
+            .field("value", &value)
+            .field("content_type", &self.content_type)
+            .field("last_modified", &self.last_modified)
+            .field("locked", &self.locked)
+            .field("tags", &self.tags)
+            .finish()
+    }
+}
+
+/// Key and label filter used to select the key values of a snapshot
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotFilter {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+impl SnapshotFilter {
+    pub fn new<S: Into<String>>(key: S, label: Option<S>) -> Self {
+        SnapshotFilter {
+            key: key.into(),
+            label: label.map(|l| l.into()),
+        }
+    }
+}
+
+/// How key values are composed into a snapshot: `Key` keeps a single key value
+/// per key (last filter wins), `KeyLabel` keeps every key and label pair
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositionType {
+    Key,
+    KeyLabel,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotStatus {
+    Provisioning,
+    Ready,
+    Archived,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshots {
+    pub items: Vec<Snapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub name: String,
+    pub status: SnapshotStatus,
+    pub filters: Vec<SnapshotFilter>,
+    pub composition_type: CompositionType,
+    #[serde(default, with = "rfc3339")]
+    pub created: Option<SystemTime>,
+    /// When an archived snapshot will be deleted
+    #[serde(default, with = "rfc3339")]
+    pub expires: Option<SystemTime>,
+    /// Seconds an archived snapshot is kept before being deleted
+    #[serde(default)]
+    pub retention_period: Option<u64>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub items_count: Option<u64>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
+    #[serde(default)]
+    pub etag: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct CreateSnapshot {
+    pub filters: Vec<SnapshotFilter>,
+    pub composition_type: CompositionType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retention_period: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct UpdateSnapshot {
+    pub status: SnapshotStatus,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OperationStatus {
+    NotStarted,
+    Running,
+    Succeeded,
+    Failed,
+    Canceled,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct OperationError {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Status of a long running operation, such as a snapshot creation
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Operation {
+    pub status: OperationStatus,
+    #[serde(default)]
+    pub error: Option<OperationError>,
+}
+
+/// Key value without label nor metadata, shared by the tests of every module
+#[cfg(test)]
+pub(crate) fn kv(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: value.to_owned(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn create_snapshot_should_serialize_snake_case_composition_type() {
+    let snapshot = CreateSnapshot {
+        filters: vec![SnapshotFilter::new("app/*", None)],
+        composition_type: CompositionType::KeyLabel,
+        retention_period: Some(3600),
+    };
+
+    assert_eq!(
+        serde_json::to_string(&snapshot).unwrap(),
+        r#"{"filters":[{"key":"app/*"}],"composition_type":"key_label","retention_period":3600}"#
+    );
+}
+
+#[test]
+fn snapshot_should_deserialize_service_response() {
+    let json = r#"{
+        "etag": "4f6dd610dd5e4deebc7fbaef685fb903",
+        "name": "release-1.0",
+        "status": "ready",
+        "filters": [{ "key": "app/*", "label": "prod" }],
+        "composition_type": "key",
+        "created": "2023-10-01T00:00:00+00:00",
+        "expires": null,
+        "retention_period": 2592000,
+        "size": 1024,
+        "items_count": 12,
+        "tags": {}
+    }"#;
+
+    let snapshot = serde_json::from_str::<Snapshot>(json).unwrap();
+    assert_eq!(snapshot.status, SnapshotStatus::Ready);
+    assert_eq!(snapshot.composition_type, CompositionType::Key);
+    assert_eq!(snapshot.filters[0].label, Some("prod".to_owned()));
+    assert_eq!(snapshot.items_count, Some(12));
+    assert_eq!(
+        snapshot.created,
+        Some(UNIX_EPOCH + Duration::from_secs(1_696_118_400))
+    );
+    assert_eq!(snapshot.expires, None);
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<Snapshot>(&json).unwrap(), snapshot);
+}
+
+#[test]
+fn key_value_debug_should_redact_sensitive_values() {
+    let password = format!("{:?}", kv("Db:Password", "hunter2"));
+    assert!(!password.contains("hunter2"));
+    assert!(password.contains("REDACTED"));
+
+    let mut reference = kv("Db:Host", r#"{"uri":"https://vault/secrets/host"}"#);
+    reference.content_type = Some(crate::redact::KEY_VAULT_REFERENCE_CONTENT_TYPE.to_owned());
+    assert!(!format!("{:?}", reference).contains("vault/secrets"));
+
+    assert!(format!("{:?}", kv("Db:Port", "5432")).contains("5432"));
+}
+
+#[test]
+fn partial_key_value_debug_should_redact_sensitive_values() {
+    let partial = |key: Option<&str>, value: &str| PartialKeyValue {
+        key: key.map(String::from),
+        value: Some(value.to_owned()),
+        ..Default::default()
+    };
+
+    let password = format!("{:?}", partial(Some("Db:Password"), "hunter2"));
+    assert!(!password.contains("hunter2"));
+    assert!(password.contains(REDACTED));
+
+    assert!(!format!("{:?}", partial(None, "hunter2")).contains("hunter2"));
+    assert!(format!("{:?}", partial(Some("Db:Port"), "5432")).contains("5432"));
+    assert!(format!("{:?}", PartialKeyValue::default()).contains("value: None"));
+}
+
+#[test]
+fn key_values_should_serialize_every_field_but_only_send_the_writable_ones() {
+    let mut tags = HashMap::new();
+    tags.insert("owner".to_owned(), "web".to_owned());
+    let kv = KeyValue {
+        key: "UseCache".to_owned(),
+        value: "true".to_owned(),
+        label: Some("web".to_owned()),
+        etag: "4f6dd610".to_owned(),
+        last_modified: Some(UNIX_EPOCH + Duration::from_secs(1_512_441_686)),
+        locked: true,
+        tags,
+        ..Default::default()
+    };
+
+    let json = serde_json::to_string(&kv).unwrap();
+    assert_eq!(serde_json::from_str::<KeyValue>(&json).unwrap(), kv);
+    assert!(json.contains("\"etag\":\"4f6dd610\""));
+    assert!(json.contains("\"last_modified\":\"2017-12-05T02:41:26Z\""));
+
+    let body = serde_json::to_value(SetKeyValueBody::from(&kv)).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"value": "true", "content_type": null, "tags": {"owner": "web"}})
+    );
+}
+
+#[test]
+fn key_values_should_hash_regardless_of_tag_order() {
+    use std::collections::hash_map::DefaultHasher;
+
+    let tagged = |tags: &[(&str, &str)]| KeyValue {
+        tags: tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        ..kv("UseCache", "true")
+    };
+    let hash = |kv: &KeyValue| {
+        let mut hasher = DefaultHasher::new();
+        kv.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    let (first, second) = (
+        tagged(&[("a", "1"), ("b", "2")]),
+        tagged(&[("b", "2"), ("a", "1")]),
+    );
+    assert_eq!(first, second);
+    assert_eq!(hash(&first), hash(&second));
+}
+
+#[test]
+fn last_modified_should_parse_rfc3339_timestamps() {
+    let modified = |last_modified: &str| {
+        serde_json::from_value::<KeyValue>(serde_json::json!({
+            "key": "UseCache",
+            "value": "true",
+            "label": null,
+            "content_type": null,
+            "last_modified": last_modified,
+        }))
+        .map(|kv| kv.last_modified)
+    };
+
+    assert_eq!(
+        modified("2017-12-05T02:41:26+00:00").unwrap(),
+        Some(UNIX_EPOCH + Duration::from_secs(1_512_441_686))
+    );
+    assert_eq!(
+        modified("2017-12-05T03:41:26.5+01:00").unwrap(),
+        Some(UNIX_EPOCH + Duration::from_millis(1_512_441_686_500))
+    );
+    assert_eq!(
+        modified("1969-12-31T23:59:59Z").unwrap(),
+        UNIX_EPOCH.checked_sub(Duration::from_secs(1))
+    );
+    assert_eq!(
+        modified("1969-12-31T23:59:59.25Z").unwrap(),
+        UNIX_EPOCH.checked_sub(Duration::from_millis(750))
+    );
+    assert_eq!(modified("").unwrap(), None);
+    assert_eq!(
+        serde_json::from_str::<KeyValue>(
+            r#"{"key":"a","value":"","label":null,"content_type":null}"#
+        )
+        .unwrap()
+        .last_modified,
+        None
+    );
+    for invalid in &[
+        "Tue, 05 Dec 2017 02:41:26 GMT",
+        "2017-12-05T02:41:xx+00:00",
+        "2017-12-05T02:41:61+00:00",
+        "2017-12-05X02:41:26+00:00",
+        "2017-12-05T02:41-26+00:00",
+        "2017-02-31T02:41:26Z",
+        "2017-12-05T02:41:26",
+        "2017-12-05T02:41:26+24:00",
+    ] {
+        assert!(modified(invalid).is_err(), "{}", invalid);
+    }
+
+    let pre_epoch = KeyValue {
+        last_modified: UNIX_EPOCH.checked_sub(Duration::from_millis(750)),
+        ..kv("UseCache", "true")
+    };
+    let json = serde_json::to_string(&pre_epoch).unwrap();
+    assert!(json.contains("\"1969-12-31T23:59:59.25Z\""));
+    assert_eq!(serde_json::from_str::<KeyValue>(&json).unwrap(), pre_epoch);
 }
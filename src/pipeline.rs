@@ -0,0 +1,462 @@
+//! Request pipeline, an ordered chain of policies modeled after the Azure SDK one
+//!
+//! For every http attempt each policy sees the request before it is signed, then
+//! the signed request, and finally the response in reverse order. Headers added
+//! before signing are covered by the signature. A policy can short-circuit the
+//! call by answering the request itself, or ask for a retry after a response.
+//!
+//! # Examples
+//! ```no run
+//! use azure_app_configuration::pipeline::{CorrelationIdPolicy, RetryPolicy, UserAgentPolicy};
+//!
+//! let client = AzureAppConfigClient::from_connection_string(&connection_string)?
+//!     .with_policy(UserAgentPolicy::new("my-service/1.0"))
+//!     .with_policy(CorrelationIdPolicy::generated())
+//!     .with_policy(RetryPolicy::default());
+//! ```
+use crate::redact::REDACTED;
+use crate::telemetry::redact_url;
+use crate::Exception;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use http::Method;
+use std::fmt;
+use std::time::Duration;
+use url::Url;
+
+pub const USER_AGENT_HEADER: &str = "user-agent";
+pub const CORRELATION_REQUEST_ID_HEADER: &str = "x-ms-correlation-request-id";
+const AUTHORIZATION_HEADER: &str = "authorization";
+const RETRY_AFTER_MS_HEADERS: &[&str] = &["retry-after-ms", "x-ms-retry-after-ms"];
+const RETRY_AFTER_HEADER: &str = "retry-after";
+
+/// Http headers keeping their insertion order, names are case insensitive
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    items: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Every value of the header, in the order they were received
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.items
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets the header, replacing any previous value
+    pub fn set<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        let name = name.into();
+        self.remove(&name);
+        self.items.push((name, value.into()));
+    }
+
+    /// Adds the header, keeping any previous value
+    pub fn append<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        self.items.push((name.into(), value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.items.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.items.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+/// The `Authorization` header is redacted
+impl fmt::Debug for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(n, v)| {
+                if n.eq_ignore_ascii_case(AUTHORIZATION_HEADER) {
+                    (n, REDACTED)
+                } else {
+                    (n, v)
+                }
+            }))
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct PipelineRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// The url is redacted and the body, which may hold secrets, is omitted
+impl fmt::Debug for PipelineRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipelineRequest")
+            .field("method", &self.method)
+            .field("url", &redact_url(&self.url))
+            .field("headers", &self.headers)
+            .field("body_length", &self.body.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PipelineResponse {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: String,
+}
+
+impl PipelineResponse {
+    pub fn new<S: Into<String>>(status: u16, body: S) -> PipelineResponse {
+        PipelineResponse {
+            status,
+            headers: Headers::default(),
+            body: body.into(),
+        }
+    }
+}
+
+/// The body, which may hold secrets, is omitted
+impl fmt::Debug for PipelineResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PipelineResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .field("body_length", &self.body.len())
+            .finish()
+    }
+}
+
+/// State of the call a request belongs to
+#[derive(Debug, Clone)]
+pub struct PolicyContext {
+    /// Random id shared by every attempt of the call
+    pub call_id: String,
+    /// Endpoint attempt, 0 for the first endpoint and greater after failovers
+    pub attempt: usize,
+    /// Retries requested by policies on the current endpoint
+    pub retry: u32,
+}
+
+/// Step of the request pipeline, every hook does nothing by default
+pub trait Policy: Send + Sync {
+    /// Called before signing, returning a response short-circuits the request
+    fn on_request(
+        &self,
+        _context: &PolicyContext,
+        _request: &mut PipelineRequest,
+    ) -> Result<Option<PipelineResponse>, Exception> {
+        Ok(None)
+    }
+
+    /// Called after signing, headers added here are not covered by the signature
+    fn on_signed_request(
+        &self,
+        _context: &PolicyContext,
+        _request: &mut PipelineRequest,
+    ) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    /// Called in reverse order with the response, returning a delay retries the
+    /// request on the same endpoint after it
+    fn on_response(
+        &self,
+        _context: &PolicyContext,
+        _request: &PipelineRequest,
+        _response: &mut PipelineResponse,
+    ) -> Result<Option<Duration>, Exception> {
+        Ok(None)
+    }
+}
+
+/// Retries throttled and failed requests on the same endpoint, honoring the
+/// retry after headers of the service or backing off exponentially
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// First delay, doubled on every retry
+    pub delay: Duration,
+    pub max_delay: Duration,
+    /// Response statuses that are retried
+    pub statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            delay: Duration::from_millis(800),
+            max_delay: Duration::from_secs(60),
+            statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl Policy for RetryPolicy {
+    fn on_response(
+        &self,
+        context: &PolicyContext,
+        _request: &PipelineRequest,
+        response: &mut PipelineResponse,
+    ) -> Result<Option<Duration>, Exception> {
+        if context.retry >= self.max_retries || !self.statuses.contains(&response.status) {
+            return Ok(None);
+        }
+
+        let delay = retry_after(&response.headers).unwrap_or_else(|| {
+            self.delay
+                .checked_mul(1 << context.retry.min(16))
+                .unwrap_or(self.max_delay)
+        });
+        Ok(Some(delay.min(self.max_delay)))
+    }
+}
+
+/// Delay asked by the service in its retry after headers
+pub(crate) fn retry_after(headers: &Headers) -> Option<Duration> {
+    RETRY_AFTER_MS_HEADERS
+        .iter()
+        .filter_map(|h| headers.get(h))
+        .filter_map(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .next()
+        .or_else(|| {
+            headers
+                .get(RETRY_AFTER_HEADER)
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+        })
+}
+
+/// Logs every request and response at debug level, urls are redacted and
+/// neither credentials nor bodies are logged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoggingPolicy;
+
+impl Policy for LoggingPolicy {
+    fn on_signed_request(
+        &self,
+        context: &PolicyContext,
+        request: &mut PipelineRequest,
+    ) -> Result<(), Exception> {
+        log::debug!(
+            "Sending {} request to {} (call {}, attempt {}, retry {}), body of {} bytes",
+            request.method,
+            redact_url(&request.url),
+            context.call_id,
+            context.attempt,
+            context.retry,
+            request.body.len()
+        );
+        Ok(())
+    }
+
+    fn on_response(
+        &self,
+        _context: &PolicyContext,
+        request: &PipelineRequest,
+        response: &mut PipelineResponse,
+    ) -> Result<Option<Duration>, Exception> {
+        log::debug!(
+            "Received {} from {}, body of {} bytes",
+            response.status,
+            redact_url(&request.url),
+            response.body.len()
+        );
+        Ok(None)
+    }
+}
+
+/// Sets the `User-Agent` header, replacing the telemetry one
+#[derive(Debug, Clone)]
+pub struct UserAgentPolicy {
+    user_agent: String,
+}
+
+impl UserAgentPolicy {
+    pub fn new<S: Into<String>>(user_agent: S) -> UserAgentPolicy {
+        UserAgentPolicy {
+            user_agent: user_agent.into(),
+        }
+    }
+}
+
+impl Policy for UserAgentPolicy {
+    fn on_request(
+        &self,
+        _context: &PolicyContext,
+        request: &mut PipelineRequest,
+    ) -> Result<Option<PipelineResponse>, Exception> {
+        request
+            .headers
+            .set(USER_AGENT_HEADER, self.user_agent.as_str());
+        Ok(None)
+    }
+}
+
+/// Sets the Azure SDK telemetry `User-Agent`, with the crate version and
+/// platform, prefixed by an optional application id
+#[derive(Debug, Clone, Default)]
+pub struct TelemetryPolicy {
+    pub application_id: Option<String>,
+}
+
+impl TelemetryPolicy {
+    fn user_agent(&self) -> String {
+        let sdk = format!(
+            "azsdk-rust-app-configuration/{} ({}; {})",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        match &self.application_id {
+            Some(id) => format!("{} {}", id, sdk),
+            None => sdk,
+        }
+    }
+}
+
+impl Policy for TelemetryPolicy {
+    fn on_request(
+        &self,
+        _context: &PolicyContext,
+        request: &mut PipelineRequest,
+    ) -> Result<Option<PipelineResponse>, Exception> {
+        request.headers.set(USER_AGENT_HEADER, self.user_agent());
+        Ok(None)
+    }
+}
+
+/// Sets the `x-ms-correlation-request-id` header, either to a fixed id or to the
+/// random id of each call
+#[derive(Debug, Clone, Default)]
+pub struct CorrelationIdPolicy {
+    id: Option<String>,
+}
+
+impl CorrelationIdPolicy {
+    pub fn new<S: Into<String>>(id: S) -> CorrelationIdPolicy {
+        CorrelationIdPolicy {
+            id: Some(id.into()),
+        }
+    }
+
+    /// Uses the random id of every call, shared by its retries and failovers
+    pub fn generated() -> CorrelationIdPolicy {
+        CorrelationIdPolicy { id: None }
+    }
+}
+
+impl Policy for CorrelationIdPolicy {
+    fn on_request(
+        &self,
+        context: &PolicyContext,
+        request: &mut PipelineRequest,
+    ) -> Result<Option<PipelineResponse>, Exception> {
+        let id = self.id.as_deref().unwrap_or(&context.call_id);
+        request.headers.set(CORRELATION_REQUEST_ID_HEADER, id);
+        Ok(None)
+    }
+}
+
+/// Random version 4 uuid
+pub(crate) fn new_call_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+fn context(retry: u32) -> PolicyContext {
+    PolicyContext {
+        call_id: new_call_id(),
+        attempt: 0,
+        retry,
+    }
+}
+
+#[test]
+fn retry_policy_should_honor_retry_after_headers() {
+    let policy = RetryPolicy::default();
+    let request = PipelineRequest {
+        method: Method::GET,
+        url: "https://sample.io/kv".parse().unwrap(),
+        headers: Headers::default(),
+        body: vec![],
+    };
+
+    let mut throttled = PipelineResponse::new(429, "");
+    throttled.headers.set("Retry-After-Ms", "1500");
+    assert_eq!(
+        policy
+            .on_response(&context(0), &request, &mut throttled)
+            .unwrap(),
+        Some(Duration::from_millis(1500))
+    );
+
+    let mut unavailable = PipelineResponse::new(503, "");
+    assert_eq!(
+        policy
+            .on_response(&context(2), &request, &mut unavailable)
+            .unwrap(),
+        Some(Duration::from_millis(3200))
+    );
+    assert_eq!(
+        policy
+            .on_response(&context(3), &request, &mut unavailable)
+            .unwrap(),
+        None
+    );
+
+    let mut not_found = PipelineResponse::new(404, "");
+    assert_eq!(
+        policy
+            .on_response(&context(0), &request, &mut not_found)
+            .unwrap(),
+        None
+    );
+}
+
+#[test]
+fn headers_should_be_case_insensitive_and_redact_authorization() {
+    let mut headers = Headers::default();
+    headers.set("Authorization", "HMAC-SHA256 Signature=secret");
+    headers.set("User-Agent", "first");
+    headers.set("user-agent", "second");
+
+    assert_eq!(headers.get("USER-AGENT"), Some("second"));
+    assert_eq!(headers.iter().count(), 2);
+    assert!(!format!("{:?}", headers).contains("secret"));
+}
+
+#[test]
+fn call_ids_should_be_uuids() {
+    let id = new_call_id();
+    assert_eq!(id.len(), 36);
+    assert_eq!(&id[14..15], "4");
+    assert_ne!(id, new_call_id());
+}
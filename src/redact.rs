@@ -0,0 +1,128 @@
+//! Masking of sensitive key values in logs and `Debug` output
+//!
+//! Values of Key Vault references and of keys matching one of the sensitive key
+//! patterns are replaced by `REDACTED`. Patterns are case insensitive and `*`
+//! matches any sequence of characters.
+//!
+//! # Examples
+//! ```
+//! use azure_app_configuration::redact::is_sensitive;
+//!
+//! assert!(is_sensitive("Database:Password", None));
+//! assert!(!is_sensitive("Database:Port", None));
+//! ```
+use crate::media_type::media_type;
+use std::sync::RwLock;
+
+pub const REDACTED: &str = "REDACTED";
+pub const KEY_VAULT_REFERENCE_CONTENT_TYPE: &str =
+    "application/vnd.microsoft.appconfig.keyvaultref+json";
+
+/// Patterns used until `set_sensitive_key_patterns` is called
+pub const DEFAULT_SENSITIVE_KEY_PATTERNS: &[&str] = &[
+    "*password*",
+    "*secret*",
+    "*connectionstring*",
+    "*token*",
+    "*apikey*",
+    "*api_key*",
+    "*privatekey*",
+];
+
+static SENSITIVE_KEY_PATTERNS: RwLock<Option<Vec<String>>> = RwLock::new(None);
+
+/// Replaces the sensitive key patterns for the whole process
+/// # Examples
+/// ```
+/// use azure_app_configuration::redact::{is_sensitive, set_sensitive_key_patterns};
+///
+/// set_sensitive_key_patterns(vec!["*:credentials:*"]);
+/// assert!(is_sensitive("app:credentials:user", None));
+/// ```
+pub fn set_sensitive_key_patterns<I, S>(patterns: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let patterns = patterns.into_iter().map(|p| p.into()).collect();
+    *SENSITIVE_KEY_PATTERNS.write().unwrap() = Some(patterns);
+}
+
+/// Returns true when the value of the key must not be logged
+pub fn is_sensitive(key: &str, content_type: Option<&str>) -> bool {
+    if content_type.is_some_and(is_key_vault_reference) {
+        return true;
+    }
+
+    match &*SENSITIVE_KEY_PATTERNS.read().unwrap() {
+        Some(patterns) => patterns.iter().any(|p| glob_match(p, key)),
+        None => DEFAULT_SENSITIVE_KEY_PATTERNS
+            .iter()
+            .any(|p| glob_match(p, key)),
+    }
+}
+
+/// Returns the value, or `REDACTED` when it is sensitive
+pub fn redact_value<'a>(key: &str, content_type: Option<&str>, value: &'a str) -> &'a str {
+    if is_sensitive(key, content_type) {
+        REDACTED
+    } else {
+        value
+    }
+}
+
+pub(crate) fn is_key_vault_reference(content_type: &str) -> bool {
+    media_type(content_type) == KEY_VAULT_REFERENCE_CONTENT_TYPE
+}
+
+/// Case insensitive match where `*` stands for any sequence of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    let parts = pattern.split('*').collect::<Vec<&str>>();
+
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    // Both ends are checked before slicing, so the cuts fall on character boundaries
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text.ends_with(last) || text.len() < first.len() + last.len() {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[test]
+fn glob_should_match_wildcards_case_insensitively() {
+    assert!(glob_match("*password*", "Db:Password"));
+    assert!(glob_match("app:*:key", "App:Payments:Key"));
+    assert!(glob_match("exact", "EXACT"));
+    assert!(!glob_match("exact", "exactly"));
+    assert!(!glob_match("a*b*c", "acb"));
+    assert!(!glob_match("ab*ba", "aba"));
+    assert!(!glob_match("*:token", "éabcde"));
+    assert!(!glob_match("é*", "aé"));
+    assert!(glob_match("*:token", "Clé:Token"));
+    assert!(glob_match("é*é", "ÉtéÉ"));
+}
+
+#[test]
+fn key_vault_references_should_be_sensitive() {
+    assert!(is_sensitive(
+        "Db:Host",
+        Some("application/vnd.microsoft.appconfig.keyvaultref+json;charset=utf-8")
+    ));
+    assert_eq!(
+        redact_value("Db:Host", Some("text/plain"), "db.local"),
+        "db.local"
+    );
+}
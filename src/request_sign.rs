@@ -1,83 +1,182 @@
-use crate::client::Body;
+use crate::pipeline::PipelineRequest;
 use crate::Exception;
 use hmac::{Hmac, Mac};
-use http::Method;
 use httpdate::fmt_http_date;
 use sha2::{Digest, Sha256};
-use surf::middleware::HttpClient;
 use url::Url;
 
 type HmacSha256 = Hmac<Sha256>;
 
-const SIGNED_HEADERS: &str = "date;host;x-ms-content-sha256";
+const DATE_HEADER: &str = "x-ms-date";
+const HOST_HEADER: &str = "host";
+const CONTENT_HASH_HEADER: &str = "x-ms-content-sha256";
+const AUTHORIZATION_HEADER: &str = "Authorization";
 
-fn get_content_hash_base64(body: &Body) -> String {
+fn get_content_hash_base64(body: &[u8]) -> String {
     let mut hasher = Sha256::new();
-    hasher.input(body.value());
+    hasher.input(body);
 
     let hashed_content = hasher.result();
     base64::encode(&hashed_content)
 }
 
-fn get_hmac(secret: Vec<u8>, to_sign: String) -> String {
-    let mut mac = HmacSha256::new_varkey(&secret).expect("HMAC can take key of any size");
+fn get_hmac(secret: &[u8], to_sign: String) -> String {
+    let mut mac = HmacSha256::new_varkey(secret).expect("HMAC can take key of any size");
     mac.input(to_sign.as_bytes());
 
     let result = mac.result().code();
     base64::encode(&result)
 }
 
-pub(crate) async fn create_signed_request<S: Into<String>>(
-    access_key: S,
-    secret: Vec<u8>,
-    url: &Url,
-    body: Body,
-    method: Method,
-) -> Result<surf::Request<impl HttpClient>, Exception> {
-    let host = url.host().unwrap().to_string();
+/// Returns the value of the host header for the given url, including the port
+/// when it is not the default one for the url scheme
+fn get_host(url: &Url) -> Result<String, Exception> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| format!("Url {} has no host", url))?;
+
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
 
+/// Builds the HMAC string to sign, that is the verb, the path with query
+/// and the signed header values separated by `;`
+fn get_string_to_sign(verb: &str, url: &Url, headers: &[(&str, String)]) -> String {
     let path = match url.query() {
-        Some(_) => format!("{}?{}", url.path(), url.query().unwrap()),
+        Some(q) => format!("{}?{}", url.path(), q),
         None => url.path().to_string(),
     };
 
-    let verb = method.to_string().to_uppercase();
-    let utc = fmt_http_date(std::time::SystemTime::now());
+    let values = headers
+        .iter()
+        .map(|(_, v)| v.as_str())
+        .collect::<Vec<&str>>()
+        .join(";");
 
-    let content_hash = get_content_hash_base64(&body);
+    format!("{}\n{}\n{}", verb, path, values)
+}
 
-    let to_sign = format!("{}\n{}\n{};{};{}", verb, path, utc, host, content_hash);
+/// Signs the request with the HMAC-SHA256 authentication scheme, adding the
+/// `x-ms-date`, `host`, `x-ms-content-sha256` and `Authorization` headers.
+/// # Arguments
+/// * `extra_headers` - Headers already on the request (such as `If-Match` or
+///   `Accept-Datetime`) to be covered by the signature too, every one when `None`
+pub(crate) fn sign_request(
+    access_key: &str,
+    secret: &[u8],
+    extra_headers: Option<&[String]>,
+    request: &mut PipelineRequest,
+) -> Result<(), Exception> {
+    let host = get_host(&request.url)?;
+    let verb = request.method.to_string().to_uppercase();
+    let utc = fmt_http_date(std::time::SystemTime::now());
+    let content_hash = get_content_hash_base64(&request.body);
+
+    for name in &[
+        DATE_HEADER,
+        HOST_HEADER,
+        CONTENT_HASH_HEADER,
+        AUTHORIZATION_HEADER,
+    ] {
+        request.headers.remove(name);
+    }
+
+    let added_headers = [
+        (DATE_HEADER, utc),
+        (HOST_HEADER, host),
+        (CONTENT_HASH_HEADER, content_hash),
+    ];
+    let mut signed_headers = added_headers.to_vec();
+    signed_headers.extend(
+        request
+            .headers
+            .iter()
+            .filter(|(n, _)| {
+                extra_headers.is_none_or(|extra| extra.iter().any(|e| e.eq_ignore_ascii_case(n)))
+            })
+            .map(|(n, v)| (n, v.to_owned())),
+    );
 
+    let to_sign = get_string_to_sign(&verb, &request.url, &signed_headers);
     let encoded_signature = get_hmac(secret, to_sign);
 
-    let mut request = surf::Request::new(method, url.clone());
-    let mut h = request.headers();
+    let signed_header_names = signed_headers
+        .iter()
+        .map(|(n, _)| n.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(";");
 
     let auth_value = format!(
         "HMAC-SHA256 Credential={}&SignedHeaders={}&Signature={}",
-        access_key.into(),
-        SIGNED_HEADERS,
-        encoded_signature
+        access_key, signed_header_names, encoded_signature
     );
 
-    log::debug!(
-        "Request signed with headers\n \
-         Date: {}\n \
-         x-ms-content-sha256: {}\n \
-         Authorization: {}",
-        &utc,
-        &content_hash,
-        &auth_value
-    );
+    log::debug!("Request signed with headers: {}", &signed_header_names);
+
+    for (name, value) in added_headers {
+        request.headers.set(name, value);
+    }
+    request.headers.set(AUTHORIZATION_HEADER, auth_value);
+
+    Ok(())
+}
+
+#[test]
+fn host_should_include_non_default_port() {
+    let url = "https://sample.azconfig.io:8443/kv".parse::<Url>().unwrap();
+    assert_eq!(get_host(&url).unwrap(), "sample.azconfig.io:8443");
+
+    let url = "https://sample.azconfig.io:443/kv".parse::<Url>().unwrap();
+    assert_eq!(get_host(&url).unwrap(), "sample.azconfig.io");
+}
 
-    log::debug!("Request body size: {}", body.len());
+#[test]
+fn host_should_fail_for_hostless_url() {
+    let url = "data:text/plain,sample".parse::<Url>().unwrap();
+    assert!(get_host(&url).is_err());
+}
 
-    h.insert("Date", utc);
-    h.insert("x-ms-content-sha256", content_hash);
-    h.insert("Authorization", auth_value);
-    h.insert("host", url.host().unwrap().to_string());
+#[test]
+fn string_to_sign_should_contain_path_query_and_header_values() {
+    let url = "https://sample.azconfig.io/kv/key?label=lbl"
+        .parse::<Url>()
+        .unwrap();
+    let headers = vec![
+        ("x-ms-date", "date".to_owned()),
+        ("host", "sample.azconfig.io".to_owned()),
+        ("x-ms-content-sha256", "hash".to_owned()),
+        ("If-Match", "\"etag\"".to_owned()),
+    ];
+
+    assert_eq!(
+        get_string_to_sign("PUT", &url, &headers),
+        "PUT\n/kv/key?label=lbl\ndate;sample.azconfig.io;hash;\"etag\""
+    );
+}
 
-    request = request.body_bytes(body.value());
+#[test]
+fn only_the_chosen_extra_headers_should_be_signed() {
+    use crate::pipeline::Headers;
 
-    Ok(request)
+    let mut request = PipelineRequest {
+        method: http::Method::PUT,
+        url: "https://sample.azconfig.io/kv/key".parse().unwrap(),
+        headers: Headers::default(),
+        body: vec![],
+    };
+    request.headers.set("If-Match", "\"etag\"");
+    request.headers.set("User-Agent", "sample/1.0");
+
+    sign_request(
+        "id",
+        b"secret",
+        Some(&["if-match".to_owned()]),
+        &mut request,
+    )
+    .unwrap();
+
+    let authorization = request.headers.get(AUTHORIZATION_HEADER).unwrap();
+    assert!(authorization.contains("SignedHeaders=x-ms-date;host;x-ms-content-sha256;if-match&"));
 }
@@ -0,0 +1,108 @@
+//! Results along with the metadata of the response they come from, for caching,
+//! concurrency control and support tickets
+use crate::pipeline::{retry_after, Headers, PipelineResponse};
+use crate::sync_token::SYNC_TOKEN_HEADER;
+use crate::telemetry::{CORRELATION_REQUEST_ID_HEADER, REQUEST_ID_HEADER};
+use std::time::Duration;
+
+const ETAG_HEADER: &str = "etag";
+const LAST_MODIFIED_HEADER: &str = "last-modified";
+
+#[derive(Debug, Clone)]
+pub struct Response<T> {
+    value: T,
+    status: u16,
+    headers: Headers,
+    endpoint: String,
+}
+
+impl<T> Response<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Every response header
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Endpoint, primary or replica, that served the request
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.headers.get(ETAG_HEADER)
+    }
+
+    pub fn last_modified(&self) -> Option<&str> {
+        self.headers.get(LAST_MODIFIED_HEADER)
+    }
+
+    /// Id of the request in the service, to be quoted in support tickets
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers.get(REQUEST_ID_HEADER)
+    }
+
+    pub fn correlation_request_id(&self) -> Option<&str> {
+        self.headers.get(CORRELATION_REQUEST_ID_HEADER)
+    }
+
+    pub fn sync_token(&self) -> Option<&str> {
+        self.headers.get(SYNC_TOKEN_HEADER)
+    }
+
+    /// Delay asked by the service before sending more requests, from the
+    /// `retry-after-ms`, `x-ms-retry-after-ms` or `Retry-After` headers
+    pub fn retry_after(&self) -> Option<Duration> {
+        retry_after(&self.headers)
+    }
+
+    /// Converts the value, keeping the metadata
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Response<U> {
+        Response {
+            value: f(self.value),
+            status: self.status,
+            headers: self.headers,
+            endpoint: self.endpoint,
+        }
+    }
+}
+
+impl Response<String> {
+    pub(crate) fn new(response: PipelineResponse, endpoint: String) -> Response<String> {
+        Response {
+            value: response.body,
+            status: response.status,
+            headers: response.headers,
+            endpoint,
+        }
+    }
+}
+
+#[test]
+fn metadata_should_come_from_the_headers() {
+    let mut response = PipelineResponse::new(200, "{}");
+    response.headers.set("ETag", "\"4f6dd610\"");
+    response.headers.set("x-ms-request-id", "7a1b");
+    response.headers.set("Sync-Token", "id=value;sn=1");
+    response.headers.set("Retry-After", "2");
+
+    let response = Response::new(response, "https://sample.io".to_owned()).map(|body| body.len());
+
+    assert_eq!(*response.value(), 2);
+    assert_eq!(response.etag(), Some("\"4f6dd610\""));
+    assert_eq!(response.request_id(), Some("7a1b"));
+    assert_eq!(response.sync_token(), Some("id=value;sn=1"));
+    assert_eq!(response.retry_after(), Some(Duration::from_secs(2)));
+    assert_eq!(response.last_modified(), None);
+    assert_eq!(response.endpoint(), "https://sample.io");
+}
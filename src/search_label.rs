@@ -1,15 +1,16 @@
-use std::string::ToString;
+use std::fmt;
 
+#[derive(Debug, Clone, Copy)]
 pub enum SearchLabel<'a> {
     All,
     For(&'a str),
 }
 
-impl<'a> ToString for SearchLabel<'a> {
-    fn to_string(&self) -> String {
+impl<'a> fmt::Display for SearchLabel<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SearchLabel::All => String::new(),
-            SearchLabel::For(v) => v.to_string(),
+            SearchLabel::All => Ok(()),
+            SearchLabel::For(v) => write!(f, "{}", v),
         }
     }
 }
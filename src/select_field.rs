@@ -0,0 +1,59 @@
+use std::fmt;
+
+/// Key value fields that can be requested with `$select`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectField {
+    Key,
+    Label,
+    Value,
+    ContentType,
+    Etag,
+    LastModified,
+    Locked,
+    Tags,
+}
+
+impl fmt::Display for SelectField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SelectField::Key => "key",
+            SelectField::Label => "label",
+            SelectField::Value => "value",
+            SelectField::ContentType => "content_type",
+            SelectField::Etag => "etag",
+            SelectField::LastModified => "last_modified",
+            SelectField::Locked => "locked",
+            SelectField::Tags => "tags",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Joins the fields into the comma separated `$select` query value, `None` when
+/// no field is given so that the parameter is left out and every field returned
+pub(crate) fn to_select_value(fields: &[SelectField]) -> Option<String> {
+    if fields.is_empty() {
+        return None;
+    }
+
+    Some(
+        fields
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<String>>()
+            .join(","),
+    )
+}
+
+#[test]
+fn select_value_test() {
+    assert_eq!(
+        to_select_value(&[SelectField::Key, SelectField::Etag]).unwrap(),
+        "key,etag"
+    );
+    assert_eq!(
+        to_select_value(&[SelectField::ContentType, SelectField::LastModified]).unwrap(),
+        "content_type,last_modified"
+    );
+    assert_eq!(to_select_value(&[]), None);
+}
@@ -0,0 +1,315 @@
+//! Fluent writes of key values, mixing `&str` and `String` freely and adding
+//! preconditions or a lock after the write
+use crate::client::{AzureAppConfigClient, Precondition};
+use crate::model::KeyValue;
+use crate::response::Response;
+use crate::search_label::SearchLabel;
+use crate::Exception;
+use std::error::Error;
+use std::fmt;
+
+/// Write of a key value started with `AzureAppConfigClient::set_key_request`
+/// and executed with `send`
+#[derive(Debug)]
+pub struct SetKeyRequest<'a> {
+    client: &'a AzureAppConfigClient,
+    key: String,
+    label: Option<String>,
+    pub(crate) key_value: KeyValue,
+    precondition: Option<Precondition>,
+    lock: bool,
+    /// Error of a setter, returned by `send`
+    error: Option<Exception>,
+}
+
+/// Error of a locking `SetKeyRequest` whose write succeeded but whose lock failed,
+/// the key value stays written and unlocked. The lock fails with a 412 status when
+/// the key value was modified between the write and the lock
+#[derive(Debug)]
+pub struct SetKeyLockError {
+    written: Response<KeyValue>,
+    error: Exception,
+}
+
+impl SetKeyLockError {
+    /// Key value as written, before the failed lock
+    pub fn written(&self) -> &Response<KeyValue> {
+        &self.written
+    }
+
+    pub fn into_written(self) -> Response<KeyValue> {
+        self.written
+    }
+}
+
+impl Error for SetKeyLockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+impl fmt::Display for SetKeyLockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Key {} was written but could not be locked: {}",
+            self.written.value().key,
+            self.error
+        )
+    }
+}
+
+impl AzureAppConfigClient {
+    /// Starts writing the key, with an empty value and no label unless set
+    /// # Examples
+    /// ```no run
+    /// let kv = app_config_client
+    ///     .set_key_request("UseCache")
+    ///     .value("true")
+    ///     .label(String::from("PublicWebsite"))
+    ///     .tag("owner", "web")
+    ///     .if_match(&etag)
+    ///     .lock()
+    ///     .send()
+    ///     .await?;
+    /// ```
+    pub fn set_key_request<S: Into<String>>(&self, key: S) -> SetKeyRequest<'_> {
+        SetKeyRequest {
+            client: self,
+            key: key.into(),
+            label: None,
+            key_value: KeyValue::default(),
+            precondition: None,
+            lock: false,
+            error: None,
+        }
+    }
+}
+
+impl<'a> SetKeyRequest<'a> {
+    pub fn value<S: Into<String>>(mut self, value: S) -> Self {
+        self.key_value.value = value.into();
+        self
+    }
+
+    /// Label of the key value, which is written without a label when not set
+    pub fn label<S: Into<String>>(mut self, label: S) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.key_value.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Removes the content type, as for plain text values
+    pub fn no_content_type(mut self) -> Self {
+        self.key_value.content_type = None;
+        self
+    }
+
+    pub fn tag<K: Into<String>, V: Into<String>>(mut self, name: K, value: V) -> Self {
+        self.key_value.tags.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn tags<I, K, V>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.key_value
+            .tags
+            .extend(tags.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Only writes when the key value exists with the ETag, failing with a 412
+    /// status otherwise
+    pub fn if_match<S: Into<String>>(mut self, etag: S) -> Self {
+        self.precondition = Some(Precondition::IfMatch(etag.into()));
+        self
+    }
+
+    /// Only writes when the key value does not exist, failing with a 412 status otherwise
+    pub fn if_none_match(mut self) -> Self {
+        self.precondition = Some(Precondition::IfNoneMatch);
+        self
+    }
+
+    /// Locks the key value once written, making it read only. The lock only applies
+    /// to the written ETag, if it fails `send` returns a `SetKeyLockError` carrying
+    /// the written key value
+    pub fn lock(mut self) -> Self {
+        self.lock = true;
+        self
+    }
+
+    pub(crate) fn fail(mut self, error: Exception) -> Self {
+        self.error.get_or_insert(error);
+        self
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(key = %self.key, label = self.label.as_deref().unwrap_or(""))
+        )
+    )]
+    pub async fn send(self) -> Result<KeyValue, Exception> {
+        Ok(self.send_with_response().await?.into_value())
+    }
+
+    /// Writes the key value, returning the metadata of the last response, the
+    /// lock one when locking
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(key = %self.key, label = self.label.as_deref().unwrap_or(""))
+        )
+    )]
+    pub async fn send_with_response(mut self) -> Result<Response<KeyValue>, Exception> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        // The service writes and locks the key value without a label when the
+        // label is left out, as it is for `SearchLabel::All`
+        let label = match &self.label {
+            Some(l) => SearchLabel::For(l),
+            None => SearchLabel::All,
+        };
+
+        let written = self
+            .client
+            .put_key_value(
+                self.key.as_str(),
+                label,
+                &self.key_value,
+                self.precondition.as_ref(),
+            )
+            .await?;
+
+        if !self.lock {
+            return Ok(written);
+        }
+
+        let precondition = Precondition::IfMatch(written.value().etag.clone());
+        match self
+            .client
+            .put_lock(self.key.as_str(), label, Some(&precondition))
+            .await
+        {
+            Ok(locked) => Ok(locked),
+            Err(error) => Err(SetKeyLockError { written, error }.into()),
+        }
+    }
+}
+
+#[test]
+fn set_key_requests_should_send_preconditions_and_lock() {
+    use crate::pipeline::{PipelineRequest, PipelineResponse, Policy, PolicyContext};
+    use std::sync::{Arc, Mutex};
+
+    /// Method, path, If-Match header and body of a request
+    type Sent = (String, String, Option<String>, String);
+
+    #[derive(Default)]
+    struct Store(Mutex<Vec<Sent>>);
+
+    impl Policy for Store {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            self.0.lock().unwrap().push((
+                request.method.to_string(),
+                request.url.path().to_owned(),
+                request.headers.get("If-Match").map(String::from),
+                String::from_utf8(request.body.clone()).unwrap(),
+            ));
+            Ok(Some(PipelineResponse::new(
+                200,
+                r#"{"etag":"5f7ee721","key":"UseCache","label":"web","value":"true","content_type":"text/plain","last_modified":"2017-12-05T02:41:26+00:00","locked":true,"tags":{}}"#,
+            )))
+        }
+    }
+
+    let store = Arc::new(Store::default());
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![store.clone()]);
+
+    let kv = async_std::task::block_on(
+        client
+            .set_key_request("UseCache")
+            .value(String::from("true"))
+            .label("web")
+            .content_type("text/plain")
+            .tag("owner", String::from("web"))
+            .if_match("4f6dd610")
+            .lock()
+            .send(),
+    )
+    .unwrap();
+
+    assert!(kv.locked);
+    let requests = store.0.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].0, "PUT");
+    assert_eq!(requests[0].1, "/kv/UseCache");
+    assert_eq!(requests[0].2.as_deref(), Some("\"4f6dd610\""));
+    assert!(requests[0].3.contains("\"owner\":\"web\""));
+    assert!(requests[0].3.contains("\"content_type\":\"text/plain\""));
+    assert_eq!(requests[1].1, "/locks/UseCache");
+    assert_eq!(requests[1].2.as_deref(), Some("\"5f7ee721\""));
+}
+
+#[test]
+fn failed_locks_should_return_the_written_key_value() {
+    use crate::error::HttpError;
+    use crate::pipeline::{PipelineRequest, PipelineResponse, Policy, PolicyContext};
+    use std::sync::Arc;
+
+    /// Writes the key value and fails the lock as if it was modified in between
+    struct ModifiedBeforeLock;
+
+    impl Policy for ModifiedBeforeLock {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            Ok(Some(if request.url.path().starts_with("/locks/") {
+                PipelineResponse::new(412, "")
+            } else {
+                PipelineResponse::new(
+                    200,
+                    r#"{"etag":"5f7ee721","key":"UseCache","label":null,"value":"true","content_type":null,"last_modified":"2017-12-05T02:41:26+00:00","locked":false,"tags":{}}"#,
+                )
+            }))
+        }
+    }
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![Arc::new(ModifiedBeforeLock)]);
+
+    let error = async_std::task::block_on(
+        client
+            .set_key_request("UseCache")
+            .value("true")
+            .lock()
+            .send(),
+    )
+    .unwrap_err();
+
+    let error = error.downcast_ref::<SetKeyLockError>().unwrap();
+    assert_eq!(error.written().value().etag, "5f7ee721");
+    assert!(!error.written().value().locked);
+    let source = error.source().unwrap().downcast_ref::<HttpError>().unwrap();
+    assert_eq!(source.status(), 412);
+}
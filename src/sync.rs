@@ -0,0 +1,375 @@
+use crate::client::{AzureAppConfigClient, Precondition};
+use crate::diff::{Change, Diff};
+use crate::error::{HttpError, LOCKED_STATUS, PRECONDITION_FAILED_STATUS};
+use crate::model::KeyValue;
+use crate::search_label::SearchLabel;
+use crate::Exception;
+use futures::stream::{self, StreamExt};
+
+/// What to do with the tags of key values that already exist in the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagPolicy {
+    /// Replace the target tags with the source ones
+    Overwrite,
+    /// Keep the target tags, source tags are only used for new key values
+    Preserve,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    /// Label of the key values created in the target, `None` for no label.
+    /// Existing key values keep their own label
+    pub label: Option<String>,
+    /// Compute the report without modifying the target
+    pub dry_run: bool,
+    /// Remove target key values missing in the source
+    pub prune: bool,
+    pub tags: TagPolicy,
+    /// Maximum number of requests in flight
+    pub max_concurrency: usize,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            label: None,
+            dry_run: false,
+            prune: false,
+            tags: TagPolicy::Overwrite,
+            max_concurrency: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug)]
+pub enum SyncOutcome {
+    /// The change was written, with the resulting key value for creations and updates
+    /// and the removed one for deletions
+    Applied(Option<KeyValue>),
+    /// The change would be applied, nothing was written
+    DryRun,
+    SkippedLocked,
+    /// The key value is missing in the source but pruning is disabled
+    SkippedNotPruned,
+    /// Only tags differ and they are preserved
+    SkippedUnchanged,
+    /// The target key value was modified since the diff was computed
+    Conflict,
+    Failed(Exception),
+}
+
+#[derive(Debug)]
+pub struct SyncItemResult {
+    pub key: String,
+    pub action: SyncAction,
+    pub outcome: SyncOutcome,
+}
+
+#[derive(Debug)]
+pub struct SyncReport {
+    /// Results ordered by key
+    pub items: Vec<SyncItemResult>,
+}
+
+impl SyncReport {
+    pub fn applied(&self) -> impl Iterator<Item = &SyncItemResult> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, SyncOutcome::Applied(_)))
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = &SyncItemResult> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, SyncOutcome::Conflict))
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &SyncItemResult> {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.outcome, SyncOutcome::Failed(_)))
+    }
+
+    /// Returns true when there are no failures nor conflicts
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none() && self.conflicts().next().is_none()
+    }
+}
+
+/// Applies a diff to the target store. Writes are guarded by etags so key values
+/// modified since the diff was computed are reported as conflicts instead of overwritten
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::diff::{diff, Selector};
+/// use azure_app_configuration::sync::{apply, SyncOptions};
+///
+/// let changes = diff(
+///     Selector::Store { client: &app_config_client, label: SearchLabel::For("staging") },
+///     Selector::Store { client: &app_config_client, label: SearchLabel::For("prod") },
+/// )
+/// .await?;
+///
+/// let options = SyncOptions {
+///     label: Some("prod".to_owned()),
+///     dry_run: true,
+///     ..Default::default()
+/// };
+/// let report = apply(&app_config_client, &changes, &options).await;
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(
+            label = options.label.as_deref().unwrap_or(""),
+            changes = diff.changes.len(),
+            dry_run = options.dry_run
+        )
+    )
+)]
+pub async fn apply(
+    client: &AzureAppConfigClient,
+    diff: &Diff,
+    options: &SyncOptions,
+) -> SyncReport {
+    let items = stream::iter(diff.changes.iter().cloned())
+        .map(|change| apply_change(client, change, options))
+        .buffered(options.max_concurrency.max(1))
+        .collect()
+        .await;
+
+    SyncReport { items }
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(key = %change.key())))]
+async fn apply_change(
+    client: &AzureAppConfigClient,
+    change: Change,
+    options: &SyncOptions,
+) -> SyncItemResult {
+    let key = change.key().to_string();
+    let (action, outcome) = match &change {
+        Change::Added(source) => {
+            let kv = KeyValue {
+                value: source.value.clone(),
+                content_type: source.content_type.clone(),
+                tags: source.tags.clone(),
+                ..Default::default()
+            };
+            let outcome = if options.dry_run {
+                SyncOutcome::DryRun
+            } else {
+                let result = client
+                    .put_key_value(
+                        key.clone(),
+                        search_label(&options.label),
+                        &kv,
+                        Some(&Precondition::IfNoneMatch),
+                    )
+                    .await;
+                into_outcome(result.map(|r| Some(r.into_value())))
+            };
+            (SyncAction::Create, outcome)
+        }
+        Change::Modified { source, target } => {
+            let tags = match options.tags {
+                TagPolicy::Overwrite => source.tags.clone(),
+                TagPolicy::Preserve => target.tags.clone(),
+            };
+            let kv = KeyValue {
+                value: source.value.clone(),
+                content_type: source.content_type.clone(),
+                tags,
+                ..Default::default()
+            };
+
+            let outcome = if target.locked {
+                SyncOutcome::SkippedLocked
+            } else if kv.value == target.value
+                && kv.content_type == target.content_type
+                && kv.tags == target.tags
+            {
+                SyncOutcome::SkippedUnchanged
+            } else if options.dry_run {
+                SyncOutcome::DryRun
+            } else {
+                let result = client
+                    .put_key_value(
+                        key.clone(),
+                        search_label(&target.label),
+                        &kv,
+                        Some(&Precondition::IfMatch(target.etag.clone())),
+                    )
+                    .await;
+                into_outcome(result.map(|r| Some(r.into_value())))
+            };
+            (SyncAction::Update, outcome)
+        }
+        Change::Removed(target) => {
+            let outcome = if !options.prune {
+                SyncOutcome::SkippedNotPruned
+            } else if target.locked {
+                SyncOutcome::SkippedLocked
+            } else if options.dry_run {
+                SyncOutcome::DryRun
+            } else {
+                let result = client
+                    .delete_key_value(
+                        key.clone(),
+                        search_label(&target.label),
+                        Some(&Precondition::IfMatch(target.etag.clone())),
+                    )
+                    .await;
+                into_outcome(result.map(|r| r.into_value()))
+            };
+            (SyncAction::Delete, outcome)
+        }
+    };
+
+    SyncItemResult {
+        key,
+        action,
+        outcome,
+    }
+}
+
+fn search_label(label: &Option<String>) -> SearchLabel<'_> {
+    match label {
+        Some(l) => SearchLabel::For(l),
+        None => SearchLabel::All,
+    }
+}
+
+fn into_outcome(result: Result<Option<KeyValue>, Exception>) -> SyncOutcome {
+    match result {
+        Ok(kv) => SyncOutcome::Applied(kv),
+        Err(e) => match e.downcast_ref::<HttpError>().map(|h| h.status()) {
+            Some(LOCKED_STATUS) => SyncOutcome::SkippedLocked,
+            Some(PRECONDITION_FAILED_STATUS) => SyncOutcome::Conflict,
+            _ => SyncOutcome::Failed(e),
+        },
+    }
+}
+
+#[test]
+fn dry_run_should_plan_changes_without_requests() {
+    use crate::model::kv;
+
+    let mut locked = kv("locked", "old");
+    locked.locked = true;
+    let mut tagged = kv("tagged", "1");
+    tagged.tags.insert("owner".to_owned(), "team".to_owned());
+
+    let diff = crate::diff::diff_key_values(
+        &[
+            kv("added", "1"),
+            kv("modified", "new"),
+            kv("locked", "new"),
+            kv("tagged", "1"),
+        ],
+        &[kv("modified", "old"), locked, kv("removed", "1"), tagged],
+    )
+    .unwrap();
+
+    // An unreachable endpoint, no request is expected in dry run mode
+    let client = AzureAppConfigClient::new("http://127.0.0.1:9", "id", "c2VjcmV0");
+    let options = SyncOptions {
+        dry_run: true,
+        tags: TagPolicy::Preserve,
+        ..Default::default()
+    };
+    let report = async_std::task::block_on(apply(&client, &diff, &options));
+
+    let outcomes = report
+        .items
+        .iter()
+        .map(|i| (i.key.as_str(), i.action, format!("{:?}", i.outcome)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outcomes,
+        vec![
+            ("added", SyncAction::Create, "DryRun".to_owned()),
+            ("locked", SyncAction::Update, "SkippedLocked".to_owned()),
+            ("modified", SyncAction::Update, "DryRun".to_owned()),
+            ("removed", SyncAction::Delete, "SkippedNotPruned".to_owned()),
+            ("tagged", SyncAction::Update, "SkippedUnchanged".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn outcome_should_map_conflicts_and_locks() {
+    let conflict: Result<Option<KeyValue>, Exception> =
+        Err(HttpError::new(412, "http://sample.io/kv/key").into());
+    assert!(matches!(into_outcome(conflict), SyncOutcome::Conflict));
+
+    let locked: Result<Option<KeyValue>, Exception> =
+        Err(HttpError::new(409, "http://sample.io/kv/key").into());
+    assert!(matches!(into_outcome(locked), SyncOutcome::SkippedLocked));
+}
+
+#[test]
+fn pruning_sync_should_compare_every_page_of_the_target() {
+    use crate::diff::{diff, Selector};
+    use crate::model::kv;
+    use crate::pipeline::{PipelineRequest, PipelineResponse, Policy, PolicyContext};
+    use std::sync::{Arc, Mutex};
+
+    /// Serves the target over two pages, recording the method of every request
+    #[derive(Default)]
+    struct TwoPages(Mutex<Vec<String>>);
+
+    impl Policy for TwoPages {
+        fn on_request(
+            &self,
+            _context: &PolicyContext,
+            request: &mut PipelineRequest,
+        ) -> Result<Option<PipelineResponse>, Exception> {
+            self.0.lock().unwrap().push(request.method.to_string());
+            let query = request.url.query().unwrap_or_default();
+            Ok(Some(if query.contains("after=") {
+                PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"key":"second","value":"2","label":"prod","content_type":null,"etag":"b"}]}"#,
+                )
+            } else {
+                PipelineResponse::new(
+                    200,
+                    r#"{"items":[{"key":"first","value":"1","label":"prod","content_type":null,"etag":"a"}],"@nextLink":"/kv?after=Zmlyc3Q%3D&label=prod"}"#,
+                )
+            }))
+        }
+    }
+
+    let pages = Arc::new(TwoPages::default());
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0")
+        .with_pipeline(vec![pages.clone()]);
+    let options = SyncOptions {
+        label: Some("prod".to_owned()),
+        prune: true,
+        ..Default::default()
+    };
+
+    let report = async_std::task::block_on(async {
+        let changes = diff(
+            Selector::KeyValues(vec![kv("first", "1"), kv("second", "2")]),
+            Selector::Store {
+                client: &client,
+                label: SearchLabel::For("prod"),
+            },
+        )
+        .await
+        .unwrap();
+        apply(&client, &changes, &options).await
+    });
+
+    assert!(report.items.is_empty());
+    assert_eq!(*pages.0.lock().unwrap(), vec!["GET", "GET"]);
+}
@@ -0,0 +1,159 @@
+//! Sync tokens, which give read-your-writes consistency across replicas
+//!
+//! The service returns a `Sync-Token` header with every response and expects it
+//! back on later requests. The client keeps the latest token of every id, by
+//! sequence number, and sends them all along every request. Tokens received from
+//! Event Grid push notifications can be added with
+//! `AzureAppConfigClient::update_sync_token`.
+use crate::Exception;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+pub const SYNC_TOKEN_HEADER: &str = "Sync-Token";
+
+/// Token in the `<id>=<value>;sn=<sequence number>` format of the service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncToken {
+    id: String,
+    value: String,
+    sequence_number: u64,
+}
+
+impl SyncToken {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+}
+
+impl FromStr for SyncToken {
+    type Err = Exception;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid sync token {}", token);
+
+        let mut parts = token.trim().splitn(2, ';');
+        let (id, value) = parts
+            .next()
+            .and_then(|pair| {
+                let index = pair.find('=')?;
+                Some((pair[..index].trim(), pair[index + 1..].trim()))
+            })
+            .ok_or_else(invalid)?;
+        let sequence_number = parts
+            .next()
+            .and_then(|sn| {
+                let (name, number) = sn.split_once('=')?;
+                match name.trim() {
+                    "sn" => number.trim().parse::<u64>().ok(),
+                    _ => None,
+                }
+            })
+            .ok_or_else(invalid)?;
+
+        if id.is_empty() || value.is_empty() {
+            return Err(invalid().into());
+        }
+
+        Ok(SyncToken {
+            id: id.to_owned(),
+            value: value.to_owned(),
+            sequence_number,
+        })
+    }
+}
+
+impl fmt::Display for SyncToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={};sn={}", self.id, self.value, self.sequence_number)
+    }
+}
+
+/// Latest token of every id seen by a client
+#[derive(Debug, Default)]
+pub(crate) struct SyncTokens {
+    tokens: Mutex<BTreeMap<String, SyncToken>>,
+}
+
+impl SyncTokens {
+    /// Keeps the token unless a token with the same id and a greater sequence
+    /// number is already known
+    pub(crate) fn update(&self, token: SyncToken) {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.get(&token.id) {
+            Some(known) if known.sequence_number > token.sequence_number => {}
+            _ => {
+                tokens.insert(token.id.clone(), token);
+            }
+        }
+    }
+
+    /// Merges the comma separated tokens of a `Sync-Token` response header,
+    /// invalid ones are ignored
+    pub(crate) fn update_from_header(&self, header: &str) {
+        for token in header.split(',').filter(|t| !t.trim().is_empty()) {
+            match token.parse::<SyncToken>() {
+                Ok(token) => self.update(token),
+                Err(e) => log::warn!("Ignoring sync token: {}", e),
+            }
+        }
+    }
+
+    pub(crate) fn all(&self) -> Vec<SyncToken> {
+        self.tokens.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Value of the `Sync-Token` request header, `None` when no token is known
+    pub(crate) fn header_value(&self) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap();
+        if tokens.is_empty() {
+            return None;
+        }
+
+        Some(
+            tokens
+                .values()
+                .map(|t| format!("{}={}", t.id, t.value))
+                .collect::<Vec<String>>()
+                .join(","),
+        )
+    }
+}
+
+#[test]
+fn sync_tokens_should_parse_values_with_equal_signs() {
+    let token = "zAJw6V16=NDoxNyM0MDg0NjA2==;sn=4084606"
+        .parse::<SyncToken>()
+        .unwrap();
+
+    assert_eq!(token.id(), "zAJw6V16");
+    assert_eq!(token.value(), "NDoxNyM0MDg0NjA2==");
+    assert_eq!(token.sequence_number(), 4084606);
+    assert_eq!(token.to_string(), "zAJw6V16=NDoxNyM0MDg0NjA2==;sn=4084606");
+
+    assert!("zAJw6V16=value".parse::<SyncToken>().is_err());
+    assert!("zAJw6V16;sn=1".parse::<SyncToken>().is_err());
+    assert!("zAJw6V16=value;sn=next".parse::<SyncToken>().is_err());
+}
+
+#[test]
+fn sync_tokens_should_keep_the_latest_sequence_number_per_id() {
+    let tokens = SyncTokens::default();
+    assert_eq!(tokens.header_value(), None);
+
+    tokens.update_from_header("b=second;sn=2, a=first;sn=5,invalid");
+    tokens.update_from_header("a=older;sn=4");
+    tokens.update_from_header("b=newer;sn=3");
+
+    assert_eq!(tokens.header_value().unwrap(), "a=first,b=newer");
+    assert_eq!(tokens.all().len(), 2);
+}
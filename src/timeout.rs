@@ -0,0 +1,179 @@
+use crate::error::{TimeoutError, TimeoutKind};
+use crate::Exception;
+use async_std::io;
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Time limits of the client requests, `None` waits forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing the connection of every attempt, TLS handshake included.
+    /// `None` leaves the 300 seconds default of curl
+    pub connect: Option<Duration>,
+    /// Receiving the response headers of every attempt, connection included
+    pub headers: Option<Duration>,
+    /// Reading the response body of every attempt
+    pub body_read: Option<Duration>,
+    /// The whole call, including failover attempts on replicas
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Some(Duration::from_secs(10)),
+            headers: Some(Duration::from_secs(30)),
+            body_read: Some(Duration::from_secs(60)),
+            total: Some(Duration::from_secs(120)),
+        }
+    }
+}
+
+impl Timeouts {
+    /// No time limits at all
+    pub fn none() -> Self {
+        Timeouts {
+            connect: None,
+            headers: None,
+            body_read: None,
+            total: None,
+        }
+    }
+}
+
+/// Time limits of the calls made by a future, replacing the ones of the client
+#[derive(Debug, Clone, Copy)]
+enum CallTimeouts {
+    Total(Duration),
+    All(Timeouts),
+}
+
+thread_local! {
+    /// Limits of the future being polled on this thread, if any
+    static CALL_TIMEOUTS: Cell<Option<CallTimeouts>> = const { Cell::new(None) };
+}
+
+/// Limits any client call to the given duration, replacing the client total timeout
+/// whether shorter or longer. The call is cancelled, dropping its in-flight request,
+/// when the time is exceeded and a `TimeoutError` of kind `Total` is returned
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::timeout::with_timeout;
+///
+/// let kv = with_timeout(
+///     Duration::from_secs(2),
+///     app_config_client.get_key_value("ConnectionString", SearchLabel::For("Production")),
+/// )
+/// .await?;
+/// ```
+pub async fn with_timeout<T, F>(duration: Duration, future: F) -> Result<T, Exception>
+where
+    F: Future<Output = Result<T, Exception>>,
+{
+    let future = Scoped::new(CallTimeouts::Total(duration), future);
+    deadline(Some(duration), TimeoutKind::Total, None, future).await
+}
+
+/// Runs client calls with their own time limits, replacing every limit of the client
+/// # Examples
+/// ```no run
+/// use azure_app_configuration::timeout::{with_call_timeouts, Timeouts};
+///
+/// let snapshot = with_call_timeouts(
+///     Timeouts {
+///         total: Some(Duration::from_secs(900)),
+///         ..Default::default()
+///     },
+///     app_config_client.create_snapshot("release", &filters, CompositionType::Key, None),
+/// )
+/// .await?;
+/// ```
+pub async fn with_call_timeouts<T, F>(timeouts: Timeouts, future: F) -> Result<T, Exception>
+where
+    F: Future<Output = Result<T, Exception>>,
+{
+    let future = Scoped::new(CallTimeouts::All(timeouts), future);
+    deadline(timeouts.total, TimeoutKind::Total, None, future).await
+}
+
+/// Limits in effect for a client call, the ones of the client unless replaced
+/// by `with_timeout` or `with_call_timeouts`
+pub(crate) fn effective(client: &Timeouts) -> Timeouts {
+    match CALL_TIMEOUTS.with(Cell::get) {
+        None => *client,
+        Some(CallTimeouts::Total(total)) => Timeouts {
+            total: Some(total),
+            ..*client
+        },
+        Some(CallTimeouts::All(timeouts)) => timeouts,
+    }
+}
+
+/// Future polled with call limits in place, client calls read them from the
+/// thread that polls them
+struct Scoped<F> {
+    timeouts: CallTimeouts,
+    future: Pin<Box<F>>,
+}
+
+impl<F> Scoped<F> {
+    fn new(timeouts: CallTimeouts, future: F) -> Scoped<F> {
+        Scoped {
+            timeouts,
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        /// Restores the limits of the enclosing future, even on panics
+        struct Restore(Option<CallTimeouts>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CALL_TIMEOUTS.with(|c| c.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CALL_TIMEOUTS.with(|c| c.replace(Some(self.timeouts))));
+        self.future.as_mut().poll(cx)
+    }
+}
+
+pub(crate) async fn deadline<T, F>(
+    limit: Option<Duration>,
+    kind: TimeoutKind,
+    url: Option<&str>,
+    future: F,
+) -> Result<T, Exception>
+where
+    F: Future<Output = Result<T, Exception>>,
+{
+    match limit {
+        None => future.await,
+        Some(limit) => match io::timeout(limit, async { Ok(future.await) }).await {
+            Ok(result) => result,
+            Err(_) => Err(TimeoutError::new(kind, limit, url).into()),
+        },
+    }
+}
+
+#[test]
+fn deadline_should_fail_with_timeout_error() {
+    let result: Result<(), Exception> =
+        async_std::task::block_on(with_timeout(Duration::from_millis(10), async {
+            async_std::task::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        }));
+
+    let error = result.unwrap_err();
+    let timeout = error.downcast_ref::<TimeoutError>().unwrap();
+    assert_eq!(timeout.kind(), TimeoutKind::Total);
+    assert_eq!(timeout.after(), Duration::from_millis(10));
+}
@@ -0,0 +1,372 @@
+//! Typed access to key values according to their content type
+//!
+//! Values are classified by media type: feature flags and Key Vault references
+//! have their own App Configuration media types, `application/json` and any
+//! other `+json` media type hold JSON, possibly described by a `profile`
+//! parameter, and everything else is plain text.
+//!
+//! # Examples
+//! ```no run
+//! use azure_app_configuration::typed_value::TypedValue;
+//!
+//! let kv = client.get_key_value("Cache:Settings", SearchLabel::All).await?;
+//! let settings: CacheSettings = kv.as_json()?;
+//! match kv.typed_value()? {
+//!     TypedValue::FeatureFlag(flag) => println!("{} enabled: {}", flag.id, flag.enabled),
+//!     TypedValue::KeyVaultReference { uri } => println!("Secret at {}", uri),
+//!     _ => {}
+//! }
+//! ```
+use crate::media_type;
+use crate::model::KeyValue;
+use crate::redact::{is_key_vault_reference, KEY_VAULT_REFERENCE_CONTENT_TYPE};
+use crate::set_key::SetKeyRequest;
+use crate::Exception;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const FEATURE_FLAG_CONTENT_TYPE: &str =
+    "application/vnd.microsoft.appconfig.ff+json;charset=utf-8";
+/// Prefix of the keys of feature flags
+pub const FEATURE_FLAG_PREFIX: &str = ".appconfig.featureflag/";
+
+const FEATURE_FLAG_MEDIA_TYPE: &str = "application/vnd.microsoft.appconfig.ff+json";
+const PROFILE_PARAMETER: &str = "profile";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue {
+    Json {
+        value: serde_json::Value,
+        /// `profile` parameter of the content type, describing the JSON schema
+        profile: Option<String>,
+    },
+    FeatureFlag(FeatureFlag),
+    /// Reference to a Key Vault secret, whose value is not resolved
+    KeyVaultReference {
+        uri: String,
+    },
+    Text(String),
+}
+
+impl TypedValue {
+    /// Value and content type to be written, plain text has no content type
+    pub fn to_value(&self) -> Result<(String, Option<String>), Exception> {
+        Ok(match self {
+            TypedValue::Json { value, profile } => (
+                serde_json::to_string(value)?,
+                Some(match profile {
+                    Some(p) => format!("{};{}=\"{}\"", JSON_CONTENT_TYPE, PROFILE_PARAMETER, p),
+                    None => JSON_CONTENT_TYPE.to_owned(),
+                }),
+            ),
+            TypedValue::FeatureFlag(flag) => (
+                serde_json::to_string(flag)?,
+                Some(FEATURE_FLAG_CONTENT_TYPE.to_owned()),
+            ),
+            TypedValue::KeyVaultReference { uri } => (
+                serde_json::to_string(&KeyVaultReference { uri: uri.clone() })?,
+                Some(format!(
+                    "{};charset=utf-8",
+                    KEY_VAULT_REFERENCE_CONTENT_TYPE
+                )),
+            ),
+            TypedValue::Text(text) => (text.clone(), None),
+        })
+    }
+}
+
+/// Feature flag in the App Configuration schema
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeatureFlag {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub enabled: bool,
+    /// Client filters deciding who gets the feature, kept as they are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<serde_json::Value>,
+    /// Fields this schema does not know, such as `display_name`, written back as they are
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl FeatureFlag {
+    /// Key the flag is stored under, its id after `FEATURE_FLAG_PREFIX`
+    pub fn key(&self) -> String {
+        format!("{}{}", FEATURE_FLAG_PREFIX, self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyVaultReference {
+    uri: String,
+}
+
+/// Kind of value held by a content type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Json,
+    FeatureFlag,
+    KeyVaultReference,
+    Text,
+}
+
+fn classify(content_type: Option<&str>) -> (ValueKind, Option<String>) {
+    let content_type = match content_type {
+        Some(c) => c,
+        None => return (ValueKind::Text, None),
+    };
+
+    let media_type = media_type::media_type(content_type);
+    let profile = media_type::parameter(content_type, PROFILE_PARAMETER);
+
+    let kind = if media_type == FEATURE_FLAG_MEDIA_TYPE {
+        ValueKind::FeatureFlag
+    } else if is_key_vault_reference(content_type) {
+        ValueKind::KeyVaultReference
+    } else if media_type::is_json(&media_type) {
+        ValueKind::Json
+    } else {
+        ValueKind::Text
+    };
+    (kind, profile)
+}
+
+impl KeyValue {
+    /// Value classified by its content type, failing when it does not hold
+    /// what the content type announces
+    pub fn typed_value(&self) -> Result<TypedValue, Exception> {
+        Ok(match classify(self.content_type.as_deref()) {
+            (ValueKind::Json, profile) => TypedValue::Json {
+                value: self.as_json()?,
+                profile,
+            },
+            (ValueKind::FeatureFlag, _) => TypedValue::FeatureFlag(self.as_json()?),
+            (ValueKind::KeyVaultReference, _) => TypedValue::KeyVaultReference {
+                uri: self.as_json::<KeyVaultReference>()?.uri,
+            },
+            (ValueKind::Text, _) => TypedValue::Text(self.value.clone()),
+        })
+    }
+
+    /// Deserializes the value as JSON, whatever its content type
+    pub fn as_json<T: DeserializeOwned>(&self) -> Result<T, Exception> {
+        // The value is left out of the error, it may be a secret
+        serde_json::from_str(&self.value).map_err(|e| {
+            format!(
+                "Key {} does not hold the expected JSON, {:?} error at line {} column {}",
+                self.key,
+                e.classify(),
+                e.line(),
+                e.column()
+            )
+            .into()
+        })
+    }
+
+    /// `true` or `false`, case insensitive
+    pub fn as_bool(&self) -> Result<bool, Exception> {
+        match self.value.trim().to_lowercase().as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.invalid("a boolean")),
+        }
+    }
+
+    pub fn as_i64(&self) -> Result<i64, Exception> {
+        self.value
+            .trim()
+            .parse()
+            .map_err(|_| self.invalid("an integer"))
+    }
+
+    /// Duration as a number of seconds or a number followed by `ms`, `s`, `m`,
+    /// `h` or `d`, such as `90` or `1.5m`
+    pub fn as_duration(&self) -> Result<Duration, Exception> {
+        parse_duration(&self.value).ok_or_else(|| self.invalid("a duration"))
+    }
+
+    fn invalid(&self, expected: &str) -> Exception {
+        format!("Key {} does not hold {}", self.key, expected).into()
+    }
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let seconds = number.parse::<f64>().ok()?
+        * match unit.trim() {
+            "ms" => 0.001,
+            "" | "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => return None,
+        };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+impl<'a> SetKeyRequest<'a> {
+    /// Sets the value and the content type matching it
+    pub fn typed_value(self, value: &TypedValue) -> Self {
+        match value.to_value() {
+            Ok((value, Some(content_type))) => self.value(value).content_type(content_type),
+            Ok((value, None)) => self.value(value).no_content_type(),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Sets the value serialized as JSON and the `application/json` content type,
+    /// serialization errors are returned by `send`
+    pub fn json_value<T: Serialize>(self, value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(json) => self.value(json).content_type(JSON_CONTENT_TYPE),
+            Err(e) => self.fail(e.into()),
+        }
+    }
+
+    /// Sets a JSON boolean value and the `application/json` content type
+    pub fn bool_value(self, value: bool) -> Self {
+        self.value(value.to_string())
+            .content_type(JSON_CONTENT_TYPE)
+    }
+
+    /// Sets a JSON number value and the `application/json` content type
+    pub fn i64_value(self, value: i64) -> Self {
+        self.value(value.to_string())
+            .content_type(JSON_CONTENT_TYPE)
+    }
+}
+
+#[test]
+fn content_types_should_be_classified_by_media_type_and_profile() {
+    assert_eq!(classify(None), (ValueKind::Text, None));
+    assert_eq!(classify(Some("text/plain")).0, ValueKind::Text);
+    assert_eq!(classify(Some("not a media type")).0, ValueKind::Text);
+    assert_eq!(
+        classify(Some(FEATURE_FLAG_CONTENT_TYPE)).0,
+        ValueKind::FeatureFlag
+    );
+    assert_eq!(
+        classify(Some(
+            "application/vnd.microsoft.appconfig.keyvaultref+json;charset=utf-8"
+        ))
+        .0,
+        ValueKind::KeyVaultReference
+    );
+    assert_eq!(
+        classify(Some("application/problem+json")).0,
+        ValueKind::Json
+    );
+    assert_eq!(
+        classify(Some(
+            "application/json; profile=\"https://azconfig.io/mime-profiles/ai\""
+        )),
+        (
+            ValueKind::Json,
+            Some("https://azconfig.io/mime-profiles/ai".to_owned())
+        )
+    );
+}
+
+#[test]
+fn typed_accessors_should_parse_values() {
+    let kv = |value: &str, content_type: Option<&str>| KeyValue {
+        content_type: content_type.map(String::from),
+        ..crate::model::kv("Sample", value)
+    };
+
+    assert!(kv(" TRUE", None).as_bool().unwrap());
+    assert!(kv("yes", None).as_bool().is_err());
+    assert_eq!(kv("-42", None).as_i64().unwrap(), -42);
+    assert_eq!(
+        kv("1.5m", None).as_duration().unwrap(),
+        Duration::from_secs(90)
+    );
+    assert_eq!(
+        kv("250ms", None).as_duration().unwrap(),
+        Duration::from_millis(250)
+    );
+    assert_eq!(
+        kv("90", None).as_duration().unwrap(),
+        Duration::from_secs(90)
+    );
+    assert!(kv("soon", None).as_duration().is_err());
+    assert_eq!(kv("[1,2]", None).as_json::<Vec<u8>>().unwrap(), vec![1, 2]);
+
+    let flag = kv(
+        r#"{"id":"Beta","enabled":true,"conditions":{"client_filters":[]}}"#,
+        Some(FEATURE_FLAG_CONTENT_TYPE),
+    );
+    match flag.typed_value().unwrap() {
+        TypedValue::FeatureFlag(flag) => {
+            assert!(flag.enabled && flag.id == "Beta");
+            assert_eq!(flag.key(), ".appconfig.featureflag/Beta");
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    let reference = TypedValue::KeyVaultReference {
+        uri: "https://vault.azure.net/secrets/db".to_owned(),
+    };
+    let (value, content_type) = reference.to_value().unwrap();
+    assert_eq!(
+        kv(&value, content_type.as_deref()).typed_value().unwrap(),
+        reference
+    );
+
+    let error = kv("\"secret\"", None).as_json::<u32>().unwrap_err();
+    assert!(!error.to_string().contains("secret"));
+}
+
+#[test]
+fn feature_flags_should_keep_the_unknown_fields() {
+    let json = r#"{"id":"Beta","enabled":false,"display_name":"Beta features","telemetry":{"enabled":true}}"#;
+    let flag = KeyValue {
+        content_type: Some(FEATURE_FLAG_CONTENT_TYPE.to_owned()),
+        ..crate::model::kv(".appconfig.featureflag/Beta", json)
+    };
+
+    let flag = match flag.typed_value().unwrap() {
+        TypedValue::FeatureFlag(flag) => flag,
+        other => panic!("Unexpected {:?}", other),
+    };
+    assert_eq!(flag.extra["display_name"], "Beta features");
+
+    let (value, _) = TypedValue::FeatureFlag(flag).to_value().unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&value).unwrap(),
+        serde_json::from_str::<serde_json::Value>(json).unwrap()
+    );
+}
+
+#[test]
+fn typed_setters_should_set_the_content_type() {
+    use crate::client::AzureAppConfigClient;
+
+    let client = AzureAppConfigClient::new("https://sample.azconfig.io", "id", "c2VjcmV0");
+
+    let request = client.set_key_request("Ports").json_value(&vec![80, 443]);
+    assert_eq!(request.key_value.value, "[80,443]");
+    assert_eq!(
+        request.key_value.content_type.as_deref(),
+        Some(JSON_CONTENT_TYPE)
+    );
+
+    let request = request.typed_value(&TypedValue::Text("80".to_owned()));
+    assert_eq!(request.key_value.content_type, None);
+
+    let request = client.set_key_request("Enabled").bool_value(true);
+    assert_eq!(request.key_value.value, "true");
+    assert_eq!(
+        request.key_value.content_type.as_deref(),
+        Some(JSON_CONTENT_TYPE)
+    );
+}